@@ -19,6 +19,11 @@ pub mod auth {
 pub mod user {
     pub const PROFILE: &'static str = "/users/profile";
     // @method: get
+    // @method: put
+    // @post-params slogan: str
+    pub const AVATAR: &'static str = "/users/avatar";
+    // @method: put
+    // @post-params avatar: str
     pub const FAVOURITES: &'static str = "/users/favourite?page=:page&s=:sort";
     // @method: get
     // @params page: number
@@ -30,7 +35,18 @@ pub mod user {
     // @method: post
 }
 pub mod comic {
-    pub const RANKING: &'static str = "/comics/leaderboard?tt=H24&ct=VC";
+    pub const RANKING: &'static str = "/comics/leaderboard?tt=:period&ct=:kind";
+    // @method: get
+    // @params tt: RankingPeriod
+    // @params ct: RankingKind
+    pub const KNIGHT_RANKING: &'static str = "/comics/knight-leaderboard";
+    // @method: get
+    pub const COMICS: &'static str = "/comics?page=:page&:filter=:value&s=:sort";
+    // @method: get
+    // @params page: number
+    // @params c|t|a|ca|ct: ComicFilter
+    // @params s: Sort
+    pub const RANDOM: &'static str = "/comics/random";
     // @method: get
     pub const COMMENTS: &'static str = "/comics/:cid/comments?page=:page";
     // @method: get
@@ -54,6 +70,7 @@ pub mod comic {
     pub const SEARCH: &'static str = "/comics/advanced-search?page=:page&s=:sort";
     // @method: post
     // @post-params keyword: str
+    // @post-params categories: [str]
     // @post-params sort: Sort
     // @params page: number
     // @params s: Sort
@@ -65,6 +82,9 @@ pub mod game {
     pub const INFO: &'static str = "/games/:cid";
     // @method: get
     // @path_params cid: str
+    pub const LIKE: &'static str = "/games/:cid/like";
+    // @method: post
+    // @path_params cid: str
     pub const COMMENTS: &'static str = "/games/:cid/comments?page=:page";
     // @method: get
     // @path_params cid: str
@@ -77,6 +97,9 @@ pub mod comment {
     // @params page: number
 }
 pub mod other {
+    pub const INIT: &'static str = "/init?platform=:platform";
+    // @method: get
+    // @params platform: str
     pub const ANNOUNCEMENTS: &'static str = "/announcements?page=:page";
     // @method: get
     // @params page: number
@@ -85,6 +108,12 @@ pub mod other {
     // @method: get
     // @path_params cid: str
     // @params page: number
+    pub const COLLECTIONS: &'static str = "/collections";
+    // @method: get
+    pub const BANNERS: &'static str = "/banners";
+    // @method: get
+    pub const CHAT: &'static str = "/chat";
+    // @method: get
     pub const KEYWORDS: &'static str = "/keywords";
     // @method: get
     pub const CATEGORIES: &'static str = "/categories";
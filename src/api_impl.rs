@@ -1,13 +1,18 @@
-use std::{collections::HashMap, fmt::Debug, sync::Arc, time::Duration};
+use std::{collections::{HashMap, HashSet}, fmt::Debug, net::{IpAddr, SocketAddr}, path::{Path, PathBuf}, sync::Arc, time::{Duration, Instant}};
 
-use reqwest::{Proxy, RequestBuilder, ClientBuilder, Client};
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use reqwest::{header::{CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE}, Proxy, RequestBuilder, StatusCode, ClientBuilder, Client};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 
 
 use serde::{de::DeserializeOwned};
 use std::sync::RwLock;
 
-use crate::{api_type::Api, nonce, Header, error::Error, api::{self}, Response, responses::{self, ComicMetadata, Comics, PunchIn, Profile, Keywords, Categories, RecommendPicLike, Comments, Eps, Pages, Search, Games, GameInfo, ComicComment, GameComment, Announcements, Favourites, GameDownloadResponse}, ApiResult, Sort, Parmas};
+use crate::{api_type::Api, nonce, header_name, r#impl::{avatar_encode, paginate}, Header, error::Error, api::{self}, Response, responses::{self, ComicMetadata, Comics, PunchIn, Profile, Keywords, Categories, RecommendPicLike, Comments, Eps, Pages, Search, Games, GameInfo, ComicComment, GameComment, Announcements, Favourites, GameDownloadResponse, MyComment, Knights, ComicList, Comic, Init, LikeAction, ComicChildrenComment, GameChildrenComment, Collections, Banners, ChatRooms, Ep, Page, PictureDownloadResounce}, ApiResult, ComicFilter, SearchQuery, Sort, RankingPeriod, RankingKind, Parmas, throttle::RateLimiter, GamePlatform, Mirror, MirrorProbe, template, verify::image_kind, manifest::part_path};
 
+const RANDOM_STALE_ROUNDS: usize = 8;
+const MIRROR_PROBE_CONCURRENCY: usize = 4;
+const MIRROR_PROBE_TIMEOUT: Duration = Duration::from_secs(10);
 
 impl Debug for Api {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -30,6 +35,8 @@ impl Api {
             token: None,
             proxy: None,
             timeout: None,
+            addresses: Vec::new(),
+            limiter: RateLimiter::unlimited(),
             email: None,
             password: None,
         }
@@ -43,6 +50,16 @@ impl Api {
         if let Some(v) = self.timeout.as_ref() {
             client_builder = client_builder.timeout(v.clone());
         }
+        let url = reqwest::Url::parse(&api::host::DEFAULT.read().unwrap()).ok();
+        if let Some((domain, port)) = url
+            .as_ref()
+            .and_then(|url| Some((url.host_str()?, url.port_or_known_default()?)))
+            .filter(|_| !self.addresses.is_empty())
+        {
+            // one call with every address, repeated calls replace each other
+            let addresses: Vec<SocketAddr> = self.addresses.iter().map(|v| SocketAddr::new(*v, port)).collect();
+            client_builder = client_builder.resolve_to_addrs(domain, &addresses);
+        }
         *self.client.write().unwrap() = client_builder.build()?;
         Ok(())
     }
@@ -59,6 +76,27 @@ impl Api {
         Ok(())
     }
 
+    pub fn set_addresses(&mut self, addresses: Vec<IpAddr>) -> Result<(), Error> {
+        self.addresses = addresses;
+        self.reset_client()?;
+        Ok(())
+    }
+
+    pub fn apply_init(&mut self, init: &Init) -> Result<(), Error> {
+        if let Some(server) = init.image_server.as_ref().or(init.image_servers.first()) {
+            *api::host::PIC.write().unwrap() = Some(server.trim_end_matches('/').to_string());
+        }
+        let addresses: Vec<IpAddr> = init.addresses.iter().filter_map(|v| v.parse().ok()).collect();
+        if !addresses.is_empty() {
+            self.set_addresses(addresses)?;
+        }
+        Ok(())
+    }
+
+    pub fn addresses(&self) -> &[IpAddr] {
+        &self.addresses
+    }
+
     pub fn proxy(&self) -> Option<&Proxy> {
         self.proxy.as_ref()
     }
@@ -66,6 +104,19 @@ impl Api {
     pub fn timeout(&self) -> Option<&Duration> {
         self.timeout.as_ref()
     }
+
+    // Global cap in bytes per second shared by every image download; `None` lifts it.
+    pub fn set_bandwidth_limit(&self, rate: Option<u64>) {
+        self.limiter.set_rate(rate);
+    }
+
+    pub fn bandwidth_limit(&self) -> Option<u64> {
+        self.limiter.rate()
+    }
+
+    pub fn limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
     
     pub fn header(&self, method: &str, uri: &str) -> Header<'_> {
         let mut header = Header::new(method, uri, &self.nonce);
@@ -101,13 +152,83 @@ impl Api {
         ).await
     }
 
-    pub async fn comic_ranking(&self) -> ApiResult<Comics> {
+    pub fn favorites_stream(&self, sort: Sort) -> impl Stream<Item = ApiResult<Comic>> + '_ {
+        paginate(move |page| self.favorites(page, sort))
+    }
+
+    pub async fn comic_ranking(&self, period: RankingPeriod, kind: RankingKind) -> ApiResult<Comics> {
         self.send(
-            self.get(&api::host::DEFAULT.read().unwrap(), api::comic::RANKING)
+            self.get(&api::host::DEFAULT.read().unwrap(), &api::comic::RANKING
+                .replace(
+                    ":period", period.as_str()
+                )
+                .replace(
+                    ":kind", kind.as_str()
+                ))
+        ).await
+    }
+
+    pub async fn knight_ranking(&self) -> ApiResult<Knights> {
+        self.send(
+            self.get(&api::host::DEFAULT.read().unwrap(), api::comic::KNIGHT_RANKING)
         ).await
     }
 
     
+    pub async fn comics(&self, filter: &ComicFilter, page: u64, sort: Sort) -> ApiResult<ComicList> {
+        self.send(
+            self.get(&api::host::DEFAULT.read().unwrap(), &api::comic::COMICS
+                .replace(
+                    ":page", &page.to_string()
+                )
+                .replace(
+                    ":filter", filter.key()
+                )
+                .replace(
+                    ":value", &filter.encoded_value()
+                )
+                .replace(
+                    ":sort", sort.as_str()
+                ))
+        ).await
+    }
+
+    pub async fn comic_random(&self) -> ApiResult<Comics> {
+        self.send(
+            self.get(&api::host::DEFAULT.read().unwrap(), api::comic::RANDOM)
+        ).await
+    }
+
+    // Ends with `Error::RandomExhausted` when 8 calls in a row bring no new comic.
+    pub fn random_stream(&self, n: usize) -> impl Stream<Item = ApiResult<Comic>> + '_ {
+        stream::unfold((HashSet::new(), 0), move |(mut seen, stale)| async move {
+            if seen.len() >= n || stale > RANDOM_STALE_ROUNDS {
+                return None;
+            }
+            if stale == RANDOM_STALE_ROUNDS {
+                let got = seen.len();
+                return Some((vec![Err(Error::RandomExhausted { wanted: n, got })], (seen, stale + 1)));
+            }
+            Some(match self.comic_random().await {
+                Ok(mut comics) => {
+                    let mut fresh = Vec::new();
+                    for comic in std::mem::take(&mut *comics) {
+                        if seen.len() >= n {
+                            break;
+                        }
+                        if seen.insert(comic.id.clone()) {
+                            fresh.push(Ok(comic));
+                        }
+                    }
+                    let stale = if fresh.is_empty() { stale + 1 } else { 0 };
+                    (fresh, (seen, stale))
+                }
+                Err(err) => (vec![Err(err)], (seen, RANDOM_STALE_ROUNDS + 1)),
+            })
+        })
+        .flat_map(stream::iter)
+    }
+
     pub async fn comic_metadata(&self, cid: &str) -> ApiResult<ComicMetadata> {
         self.send(
             self.get(&api::host::DEFAULT.read().unwrap(), &api::comic::METADATA.replace(":cid", cid))
@@ -149,6 +270,50 @@ impl Api {
         ).await
     }
 
+    pub fn comic_eps_stream<'a>(&'a self, cid: &'a str) -> impl Stream<Item = ApiResult<Ep>> + 'a {
+        paginate(move |page| self.comic_eps(cid, page))
+    }
+
+    pub async fn comic_eps_all(&self, cid: &str) -> ApiResult<Vec<Ep>> {
+        let mut eps: Vec<Ep> = self.comic_eps_stream(cid).try_collect().await?;
+        // the listing is newest first, which gives the order of eps that lack one
+        let total = eps.len() as u64;
+        for (index, ep) in eps.iter_mut().enumerate() {
+            ep.order.get_or_insert(total - index as u64);
+        }
+        eps.sort_by_key(|ep| ep.order);
+        Ok(eps)
+    }
+
+    pub async fn episode_order(&self, cid: &str, ep: &Ep) -> ApiResult<u64> {
+        if let Some(order) = ep.order {
+            return Ok(order);
+        }
+        self.comic_eps_all(cid).await?
+            .into_iter()
+            .find(|v| v.id == ep.id)
+            .and_then(|v| v.order)
+            .ok_or_else(|| Error::NotFound(ep.id.clone()))
+    }
+
+    pub fn comic_pages_stream<'a>(&'a self, cid: &'a str, index: u64) -> impl Stream<Item = ApiResult<Page>> + 'a {
+        paginate(move |page| self.comic_pages(cid, index, page))
+    }
+
+    pub async fn episode_images(&self, cid: &str, ep: &Ep) -> ApiResult<Vec<Page>> {
+        let order = self.episode_order(cid, ep).await?;
+        self.comic_pages_stream(cid, order).try_collect().await
+    }
+
+    pub async fn comic_images(&self, cid: &str) -> ApiResult<Vec<(Ep, Vec<Page>)>> {
+        let mut images = Vec::new();
+        for ep in self.comic_eps_all(cid).await? {
+            let pages = self.episode_images(cid, &ep).await?;
+            images.push((ep, pages));
+        }
+        Ok(images)
+    }
+
     pub async fn game_comments(&self, cid: &str, page: u64) -> ApiResult<Comments<GameComment>> {
         self.send(
             self.get(&api::host::DEFAULT.read().unwrap(), &api::game::COMMENTS
@@ -159,7 +324,7 @@ impl Api {
         ).await
     }
 
-    pub async fn children_comments<T>(&self, cid: &str, page: u64) -> ApiResult<Comments<T>>
+    pub(crate) async fn children_comments<T>(&self, cid: &str, page: u64) -> ApiResult<Comments<T>>
     where T: DeserializeOwned + Debug
     {
         self.send(
@@ -171,6 +336,14 @@ impl Api {
         ).await
     }
 
+    pub async fn comic_comment_children(&self, id: &str, page: u64) -> ApiResult<Comments<ComicChildrenComment>> {
+        self.children_comments(id, page).await
+    }
+
+    pub async fn game_comment_children(&self, id: &str, page: u64) -> ApiResult<Comments<GameChildrenComment>> {
+        self.children_comments(id, page).await
+    }
+
     pub async fn games(&self, page: u64) -> ApiResult<Games> {
         self.send(
             self.get(&api::host::DEFAULT.read().unwrap(), &api::game::GAMES
@@ -187,14 +360,125 @@ impl Api {
         ).await
     }
 
+    pub async fn game_like(&self, cid: &str) -> ApiResult<LikeAction> {
+        self.send(
+            self.post(&api::host::DEFAULT.read().unwrap(), &api::game::LIKE
+                .replace(":cid", cid))
+        ).await
+    }
+
     pub async fn game_download_info_get(&self, url: &str) -> Result<GameDownloadResponse, Error> {
-        let mut game_url = reqwest::Url::parse(url).unwrap();
+        let mut game_url = reqwest::Url::parse(url).map_err(|err| Error::Download(format!("{} {}", url, err)))?;
         game_url.set_path(&format!("/api/v1{}", game_url.path()));
         Ok(self.client.read().unwrap().get(game_url)
             .header("referer", url)
             .send().await?.json().await?)
     }
 
+    // Available mirrors first, fastest first.
+    pub async fn probe_mirrors(&self, mirrors: &[Mirror]) -> Vec<MirrorProbe> {
+        let client = self.client.read().unwrap().clone();
+        let mut probes: Vec<MirrorProbe> = stream::iter(mirrors.iter().cloned())
+            .map(|mirror| {
+                let client = client.clone();
+                async move {
+                    let mut probe = MirrorProbe {
+                        mirror,
+                        status: None,
+                        latency: None,
+                        size: None,
+                        error: None,
+                    };
+                    let started = Instant::now();
+                    match client.head(&probe.mirror.url)
+                        .header(header_name::USER_AGENT, Header::USER_AGENT)
+                        .timeout(MIRROR_PROBE_TIMEOUT)
+                        .send()
+                        .await
+                    {
+                        Ok(response) => {
+                            probe.latency = Some(started.elapsed());
+                            probe.status = Some(response.status().as_u16());
+                            probe.size = response.headers().get(CONTENT_LENGTH)
+                                .and_then(|v| v.to_str().ok())
+                                .and_then(|v| v.parse().ok());
+                        }
+                        Err(err) => probe.error = Some(err.to_string()),
+                    }
+                    probe
+                }
+            })
+            .buffer_unordered(MIRROR_PROBE_CONCURRENCY)
+            .collect()
+            .await;
+        probes.sort_by_key(|probe| (!probe.is_available(), probe.latency.unwrap_or(Duration::MAX)));
+        probes
+    }
+
+    // Saves the game into `dir`, trying mirrors from the best probe down; returns the file path.
+    pub async fn download_game(&self, game: &GameInfo, platform: GamePlatform, dir: impl AsRef<Path>) -> Result<PathBuf, Error> {
+        let (links, extension) = match platform {
+            GamePlatform::Android => (&game.android_links, "apk"),
+            GamePlatform::Ios => (&game.ios_links, "ipa"),
+        };
+        let mut last_error = Error::NotFound(format!("{} has no {:?} download", game.title, platform));
+        let mut mirrors = Vec::new();
+        for link in links.iter() {
+            match self.game_download_info_get(link).await {
+                Ok(info) => mirrors.extend(info.download.mirrors()),
+                Err(err) => last_error = err,
+            }
+        }
+
+        let dir = dir.as_ref();
+        tokio::fs::create_dir_all(dir).await?;
+        let client = self.client.read().unwrap().clone();
+        // share pages and torrents answer the probe but do not serve the file itself
+        let probes = self.probe_mirrors(&mirrors).await.into_iter()
+            .filter(|probe| probe.mirror.kind.is_direct() && probe.is_available());
+        for probe in probes {
+            let name = probe.mirror.file_name().unwrap_or_else(|| format!("{}.{}", game.id, extension));
+            let path = dir.join(template::sanitize(&name, template::MAX_COMPONENT_BYTES, true));
+            let partial = part_path(&path);
+            match self.download_mirror(&client, &probe.mirror, &partial).await {
+                Ok(()) => {
+                    tokio::fs::rename(&partial, &path).await?;
+                    return Ok(path);
+                }
+                Err(err) => {
+                    let _ = tokio::fs::remove_file(&partial).await;
+                    last_error = err;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    async fn download_mirror(&self, client: &Client, mirror: &Mirror, path: &Path) -> Result<(), Error> {
+        let mut response = client.get(&mirror.url)
+            .header(header_name::USER_AGENT, Header::USER_AGENT)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            Err(Error::Download(format!("{} status {}", mirror.url, response.status())))?;
+        }
+        let expected = response.content_length();
+        let mut file = tokio::io::BufWriter::new(tokio::fs::File::create(path).await?);
+        let mut written = 0;
+        while let Some(chunk) = response.chunk().await? {
+            self.limiter.acquire(chunk.len() as u64).await;
+            file.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+        }
+        file.flush().await?;
+        match expected {
+            Some(expected) if expected != written => {
+                Err(Error::Download(format!("{} expected {} bytes, got {}", mirror.url, expected, written)))
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub async fn punch_in(&self) -> ApiResult<PunchIn> {
         self.send(
             self.post(&api::host::DEFAULT.read().unwrap(), &api::user::PUNCH_IN)
@@ -207,6 +491,47 @@ impl Api {
         ).await
     }
 
+    pub async fn update_slogan(&self, slogan: &str) -> ApiResult<()> {
+        let mut payload = HashMap::new();
+        payload.insert("slogan", slogan);
+        self.send(
+            self.put(&api::host::DEFAULT.read().unwrap(), api::user::PROFILE)
+            .json(&payload)
+        ).await
+    }
+
+    pub async fn update_avatar(&self, image: &[u8]) -> ApiResult<()> {
+        let avatar = avatar_encode(image)?;
+        let mut payload = HashMap::new();
+        payload.insert("avatar", avatar.as_str());
+        self.send(
+            self.put(&api::host::DEFAULT.read().unwrap(), api::user::AVATAR)
+            .json(&payload)
+        ).await
+    }
+
+    pub async fn my_comments(&self, page: u64) -> ApiResult<Comments<MyComment>> {
+        self.send(
+            self.get(&api::host::DEFAULT.read().unwrap(), &api::user::COMMENTS
+                .replace(
+                    ":page", &page.to_string()
+                ))
+        ).await
+    }
+
+    pub fn my_comments_stream(&self) -> impl Stream<Item = ApiResult<MyComment>> + '_ {
+        paginate(move |page| self.my_comments(page))
+    }
+
+    pub async fn init(&self) -> ApiResult<Init> {
+        self.send(
+            self.get(&api::host::DEFAULT.read().unwrap(), &api::other::INIT
+                .replace(
+                    ":platform", crate::app::PLATFORM
+                ))
+        ).await
+    }
+
     pub async fn keywords(&self) -> ApiResult<Keywords> {
         self.send(
             self.get(&api::host::DEFAULT.read().unwrap(), &api::other::KEYWORDS)
@@ -222,6 +547,24 @@ impl Api {
         ).await
     }
 
+    pub async fn collections(&self) -> ApiResult<Collections> {
+        self.send(
+            self.get(&api::host::DEFAULT.read().unwrap(), api::other::COLLECTIONS)
+        ).await
+    }
+
+    pub async fn banners(&self) -> ApiResult<Banners> {
+        self.send(
+            self.get(&api::host::DEFAULT.read().unwrap(), api::other::BANNERS)
+        ).await
+    }
+
+    pub async fn chat_rooms(&self) -> ApiResult<ChatRooms> {
+        self.send(
+            self.get(&api::host::DEFAULT.read().unwrap(), api::other::CHAT)
+        ).await
+    }
+
     pub async fn categories(&self) -> ApiResult<Categories> {
         self.send(
             self.get(&api::host::DEFAULT.read().unwrap(), &api::other::CATEGORIES)
@@ -240,22 +583,140 @@ impl Api {
     }
 
     pub async fn search(&self, keyword: &str, page: u64, sort: Sort) -> ApiResult<Search> {
-        let mut payload = HashMap::new();
-        payload.insert("keyword", keyword);
-        payload.insert("sort", sort.as_str());
+        self.advanced_search(&SearchQuery::new(keyword).sort(sort), page).await
+    }
+
+    pub async fn advanced_search(&self, query: &SearchQuery, page: u64) -> ApiResult<Search> {
         self.send(
             self.post(&api::host::DEFAULT.read().unwrap(), &api::comic::SEARCH
                 .replace(
                     ":page", &page.to_string()
                 )
                 .replace(
-                    ":sort", sort.as_str()
+                    ":sort", query.sorting().as_str()
                 ))
-            .json(&payload)
+            .json(&query.payload())
         ).await
     }
 
 
+    pub(crate) async fn image_response(&self, resource: &PictureDownloadResounce) -> Result<reqwest::Response, Error> {
+        let client = self.client.read().unwrap().clone();
+        let mut last_error = Error::NotFound(resource.resource_path().to_string());
+        for url in resource.download_urls() {
+            match client.get(url.clone()).header(header_name::USER_AGENT, Header::USER_AGENT).send().await {
+                Ok(response) if response.status().is_success() => {
+                    // CDNs often send real images as octet-stream or without a type, so only error pages
+                    // are rejected here; the magic bytes are checked once the body is in
+                    match response.headers().get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) {
+                        Some(content_type) if content_type.starts_with("text/") || content_type.contains("json") => {
+                            last_error = Error::Download(format!("{} unexpected content type {}", url, content_type))
+                        }
+                        _ => return Ok(response),
+                    }
+                }
+                Ok(response) => last_error = Error::Download(format!("{} status {}", url, response.status())),
+                Err(err) => last_error = err.into(),
+            }
+        }
+        Err(last_error)
+    }
+
+    // Byte size of an image without downloading it: HEAD first, then a one-byte ranged GET.
+    pub async fn image_size(&self, resource: &PictureDownloadResounce) -> Result<Option<u64>, Error> {
+        let client = self.client.read().unwrap().clone();
+        let mut last_error = None;
+        for url in resource.download_urls() {
+            match client.head(url.clone()).header(header_name::USER_AGENT, Header::USER_AGENT).send().await {
+                Ok(response) if response.status().is_success() => {
+                    let length = response.headers().get(CONTENT_LENGTH)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.parse::<u64>().ok());
+                    if let Some(length) = length.filter(|v| *v > 0) {
+                        return Ok(Some(length));
+                    }
+                }
+                Ok(response) => {
+                    last_error = Some(Error::Download(format!("{} status {}", url, response.status())));
+                    continue;
+                }
+                Err(err) => {
+                    last_error = Some(err.into());
+                    continue;
+                }
+            }
+            match client.get(url.clone())
+                .header(header_name::USER_AGENT, Header::USER_AGENT)
+                .header(RANGE, "bytes=0-0")
+                .send()
+                .await
+            {
+                Ok(response) if response.status().is_success() => {
+                    // "bytes 0-0/<total>"
+                    let total = response.headers().get(CONTENT_RANGE)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.rsplit_once('/'))
+                        .and_then(|(_, total)| total.parse::<u64>().ok());
+                    // a server ignoring the range sends the whole file instead
+                    if total.is_none() && response.status() == StatusCode::OK {
+                        return Ok(response.content_length().filter(|v| *v > 0));
+                    }
+                    return Ok(total);
+                }
+                Ok(response) => last_error = Some(Error::Download(format!("{} status {}", url, response.status()))),
+                Err(err) => last_error = Some(err.into()),
+            }
+        }
+        match last_error {
+            Some(err) => Err(err),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn download_image(&self, resource: &PictureDownloadResounce) -> Result<Vec<u8>, Error> {
+        let mut buffer = Vec::new();
+        self.download_image_to(resource, &mut buffer).await?;
+        if image_kind(&buffer).is_none() {
+            Err(Error::InvalidImage)?;
+        }
+        Ok(buffer)
+    }
+
+    pub async fn download_image_to<W>(&self, resource: &PictureDownloadResounce, writer: &mut W) -> Result<u64, Error>
+    where W: AsyncWrite + Unpin
+    {
+        self.download_image_limited(resource, writer, None).await
+    }
+
+    // Like `download_image_to`, additionally throttled by `limit` on top of the global limiter.
+    pub async fn download_image_limited<W>(
+        &self,
+        resource: &PictureDownloadResounce,
+        writer: &mut W,
+        limit: Option<&RateLimiter>,
+    ) -> Result<u64, Error>
+    where W: AsyncWrite + Unpin
+    {
+        let mut response = self.image_response(resource).await?;
+        let expected = response.content_length();
+        let mut written = 0;
+        while let Some(chunk) = response.chunk().await? {
+            self.limiter.acquire(chunk.len() as u64).await;
+            if let Some(limit) = limit {
+                limit.acquire(chunk.len() as u64).await;
+            }
+            writer.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+        }
+        writer.flush().await?;
+        match expected {
+            Some(expected) if expected != written => {
+                Err(Error::Download(format!("{} expected {} bytes, got {}", resource.resource_path(), expected, written)))
+            }
+            _ => Ok(written),
+        }
+    }
+
     pub fn get(&self, host: &str, uri: &str) -> RequestBuilder {
         self.client.read().unwrap().get(format!("{}{}", host, uri))
             .headers(self.header("get", uri).into())
@@ -266,6 +727,11 @@ impl Api {
             .headers(self.header("post", uri).into())
     }
 
+    pub fn put(&self, host: &str, uri: &str) -> RequestBuilder {
+        self.client.read().unwrap().put(format!("{}{}", host, uri))
+            .headers(self.header("put", uri).into())
+    }
+
     pub fn head(&self, host: &str, uri: &str) -> RequestBuilder {
         self.client.read().unwrap().head(format!("{}{}", host, uri))
             .headers(self.header("head", uri).into())
@@ -280,28 +746,12 @@ impl Api {
         let res = self.client.read().unwrap().execute(req).await?;
         let text = res.text().await?;
         println!("Parsing {}", &text);
-        Ok(serde_json::from_str::<Response<T>>(&text)
-            .map(|response| {
-                if response.is_success() {
-                    Ok::<T, Error>(response.data.unwrap())
-                } else {
-                    Err(response.into())
-                }
-            })??
-            )
+        serde_json::from_str::<Response<T>>(&text)?.into_result()
     }
 
 
     #[cfg(not(feature="debug"))]
     pub async fn send<T: Debug + DeserializeOwned>(&self, builder: RequestBuilder) -> ApiResult<T> {
-        Ok(builder.send().await?.json::<Response<T>>().await
-            .map(|response| {
-                if response.is_success() {
-                    Ok::<T, Error>(response.data.unwrap())
-                } else {
-                    Err(response.into())
-                }
-            })??
-            )
+        builder.send().await?.json::<Response<T>>().await?.into_result()
     }
 }
@@ -1,4 +1,5 @@
 use std::{
+    net::IpAddr,
     ops::{Deref, DerefMut},
     sync::Arc, time::Duration,
 };
@@ -6,12 +7,16 @@ use std::{
 use reqwest::Proxy;
 use std::sync::RwLock;
 
+use crate::throttle::RateLimiter;
+
 pub struct Api {
     pub(super) nonce: String,
     pub(super) token: Option<String>,
     pub(super) proxy: Option<Proxy>,
     pub(super) timeout: Option<Duration>,
+    pub(super) addresses: Vec<IpAddr>,
     pub(super) client: Arc<RwLock<reqwest::Client>>,
+    pub(super) limiter: RateLimiter,
     pub(super) email: Option<String>,
     pub(super) password: Option<String>,
 }
@@ -0,0 +1,552 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{SinkExt, Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::{
+    net::TcpStream,
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+    time::{self, Instant},
+};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest, http::HeaderValue, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::{api, api_type::Api, app, error::Error, responses::ChatRoom};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+pub mod event {
+    pub const INIT: &'static str = "init";
+    pub const SEND_MESSAGE: &'static str = "send_message";
+    pub const BROADCAST_MESSAGE: &'static str = "broadcast_message";
+    pub const RECEIVE_NOTIFICATION: &'static str = "receive_notification";
+}
+
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub max_retries: Option<u32>,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: None,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn never() -> Self {
+        Self {
+            max_retries: Some(0),
+            ..Default::default()
+        }
+    }
+
+    pub fn delay(&self, attempt: u32) -> Option<Duration> {
+        if self.max_retries.is_some_and(|max| attempt > max) {
+            return None;
+        }
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        Some(self.initial_delay.saturating_mul(factor).min(self.max_delay))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatText {
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub name: String,
+    pub user_id: Option<String>,
+    pub title: Option<String>,
+    pub level: Option<u64>,
+    pub gender: Option<String>,
+    pub character: Option<String>,
+    pub avatar: Option<String>,
+    pub at: Option<String>,
+    pub reply: Option<String>,
+    pub reply_name: Option<String>,
+    pub platform: Option<String>,
+    #[serde(default)]
+    pub verified: bool,
+}
+
+#[derive(Debug)]
+pub enum ChatEvent {
+    Connected,
+    Message(Box<ChatText>),
+    Notification(Value),
+    Event { name: String, data: Value },
+    Reconnecting { attempt: u32, delay: Duration },
+    Disconnected,
+}
+
+#[derive(Debug, Deserialize)]
+struct Handshake {
+    #[serde(rename = "pingInterval")]
+    ping_interval: u64,
+    #[serde(rename = "pingTimeout")]
+    ping_timeout: u64,
+}
+
+pub struct ChatClient {
+    outgoing: UnboundedSender<String>,
+    events: UnboundedReceiver<ChatEvent>,
+    task: JoinHandle<()>,
+}
+
+impl ChatClient {
+    pub async fn connect(api: &Api, room: &ChatRoom) -> Result<Self, Error> {
+        Self::connect_with(api, room, ReconnectPolicy::default()).await
+    }
+
+    pub async fn connect_with(
+        api: &Api,
+        room: &ChatRoom,
+        policy: ReconnectPolicy,
+    ) -> Result<Self, Error> {
+        let token = api.token.clone().ok_or(Error::Unlogin)?;
+        let host = api::host::DEFAULT.read().unwrap().clone();
+        let profile: Value = api.send(api.get(&host, api::user::PROFILE)).await?;
+        let user = profile.get("user").cloned().unwrap_or(Value::Null);
+        Self::connect_as(&room.url, &token, user, policy).await
+    }
+
+    // Connects with an already known `user` payload, as sent in `init` and every message.
+    pub async fn connect_as(
+        url: &str,
+        token: &str,
+        user: Value,
+        policy: ReconnectPolicy,
+    ) -> Result<Self, Error> {
+        let session = Session {
+            url: endpoint(url)?,
+            token: token.to_string(),
+            user,
+            policy,
+        };
+        let connection = session.open().await?;
+        let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+        let (events_tx, events) = mpsc::unbounded_channel();
+        let task = tokio::spawn(session.run(connection, outgoing_rx, events_tx));
+        Ok(Self {
+            outgoing,
+            events,
+            task,
+        })
+    }
+
+    pub fn send(&self, text: &str) -> Result<(), Error> {
+        self.outgoing
+            .send(text.to_string())
+            .map_err(|_| Error::ChatClosed)
+    }
+}
+
+impl Drop for ChatClient {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Stream for ChatClient {
+    type Item = ChatEvent;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+struct Session {
+    url: String,
+    token: String,
+    user: Value,
+    policy: ReconnectPolicy,
+}
+
+impl Session {
+    async fn open(&self) -> Result<(Socket, Handshake), Error> {
+        let mut request = self.url.as_str().into_client_request()?;
+        request.headers_mut().insert(
+            "authorization",
+            HeaderValue::from_str(&self.token).map_err(|err| Error::Chat(err.to_string()))?,
+        );
+        let (mut socket, _) = connect_async(request).await?;
+        let handshake = loop {
+            match socket.next().await {
+                Some(Ok(Message::Text(text))) if text.starts_with('0') => {
+                    break serde_json::from_str::<Handshake>(&text[1..])?
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(err)) => Err(err)?,
+                None => Err(Error::Chat("closed during handshake".to_owned()))?,
+            }
+        };
+        socket
+            .send(Message::Text(emit(event::INIT, &self.user)))
+            .await?;
+        Ok((socket, handshake))
+    }
+
+    async fn run(
+        self,
+        mut connection: (Socket, Handshake),
+        mut outgoing: UnboundedReceiver<String>,
+        events: UnboundedSender<ChatEvent>,
+    ) {
+        loop {
+            if events.send(ChatEvent::Connected).is_err() {
+                return;
+            }
+            let (socket, handshake) = connection;
+            if !self.pump(socket, &handshake, &mut outgoing, &events).await {
+                return;
+            }
+            let mut attempt = 0;
+            connection = loop {
+                attempt += 1;
+                let Some(delay) = self.policy.delay(attempt) else {
+                    let _ = events.send(ChatEvent::Disconnected);
+                    return;
+                };
+                if events
+                    .send(ChatEvent::Reconnecting { attempt, delay })
+                    .is_err()
+                {
+                    return;
+                }
+                time::sleep(delay).await;
+                if let Ok(connection) = self.open().await {
+                    break connection;
+                }
+            };
+        }
+    }
+
+    // Returns whether the connection was lost and should be re-established.
+    async fn pump(
+        &self,
+        socket: Socket,
+        handshake: &Handshake,
+        outgoing: &mut UnboundedReceiver<String>,
+        events: &UnboundedSender<ChatEvent>,
+    ) -> bool {
+        let (mut sink, mut stream) = socket.split();
+        let mut ping = time::interval(Duration::from_millis(handshake.ping_interval));
+        let timeout = Duration::from_millis(handshake.ping_interval + handshake.ping_timeout);
+        let mut last_seen = Instant::now();
+        loop {
+            tokio::select! {
+                _ = ping.tick() => {
+                    if last_seen.elapsed() > timeout
+                        || sink.send(Message::Text("2".to_owned())).await.is_err()
+                    {
+                        return true;
+                    }
+                }
+                text = outgoing.recv() => {
+                    let Some(text) = text else {
+                        let _ = sink.close().await;
+                        return false;
+                    };
+                    let message = emit(event::SEND_MESSAGE, &self.message(&text));
+                    if sink.send(Message::Text(message)).await.is_err() {
+                        return true;
+                    }
+                }
+                message = stream.next() => {
+                    let text = match message {
+                        Some(Ok(Message::Text(text))) => text,
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return true,
+                        Some(Ok(_)) => {
+                            last_seen = Instant::now();
+                            continue;
+                        }
+                    };
+                    last_seen = Instant::now();
+                    match text.as_str() {
+                        "1" | "41" => return true,
+                        "2" => {
+                            if sink.send(Message::Text("3".to_owned())).await.is_err() {
+                                return true;
+                            }
+                        }
+                        _ => {
+                            if let Some(event) = decode(&text) {
+                                if events.send(event).is_err() {
+                                    return false;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn message(&self, text: &str) -> Value {
+        let mut payload = match &self.user {
+            Value::Object(user) => user.clone(),
+            _ => Default::default(),
+        };
+        if let Some(id) = payload.get("_id").cloned() {
+            payload.insert("user_id".to_owned(), id);
+        }
+        payload.insert("message".to_owned(), text.into());
+        payload.insert("platform".to_owned(), app::PLATFORM.into());
+        payload.insert("at".to_owned(), "".into());
+        payload.insert("reply".to_owned(), "".into());
+        payload.insert("reply_name".to_owned(), "".into());
+        Value::Object(payload)
+    }
+}
+
+fn endpoint(url: &str) -> Result<String, Error> {
+    let mut url = reqwest::Url::parse(url).map_err(|err| Error::Chat(err.to_string()))?;
+    let scheme = match url.scheme() {
+        "https" | "wss" => "wss",
+        _ => "ws",
+    };
+    url.set_scheme(scheme)
+        .map_err(|_| Error::Chat(format!("unsupported chat url {}", url)))?;
+    if !url.path().starts_with("/socket.io") {
+        url.set_path("/socket.io/");
+    }
+    url.query_pairs_mut()
+        .append_pair("EIO", "3")
+        .append_pair("transport", "websocket");
+    Ok(url.to_string())
+}
+
+fn emit(name: &str, data: &Value) -> String {
+    format!("42{}", serde_json::json!([name, data]))
+}
+
+fn decode(text: &str) -> Option<ChatEvent> {
+    let payload = text
+        .strip_prefix("42")?
+        .trim_start_matches(|c: char| c.is_ascii_digit());
+    let mut frame: Vec<Value> = serde_json::from_str(payload).ok()?;
+    if frame.is_empty() {
+        return None;
+    }
+    let name = frame.remove(0).as_str()?.to_string();
+    let data = frame.into_iter().next().unwrap_or(Value::Null);
+    Some(match name.as_str() {
+        event::BROADCAST_MESSAGE => match ChatText::deserialize(&data) {
+            Ok(text) => ChatEvent::Message(Box::new(text)),
+            Err(_) => ChatEvent::Event { name, data },
+        },
+        event::RECEIVE_NOTIFICATION => ChatEvent::Notification(data),
+        _ => ChatEvent::Event { name, data },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
+    use super::*;
+
+    const HANDSHAKE: &str = r#"0{"sid":"test","upgrades":[],"pingInterval":25000,"pingTimeout":5000}"#;
+    const WAIT: Duration = Duration::from_secs(5);
+
+    type ServerSocket = WebSocketStream<TcpStream>;
+
+    async fn listen() -> (TcpListener, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        (listener, url)
+    }
+
+    async fn accept(listener: &TcpListener, handshake: &str) -> ServerSocket {
+        let (stream, _) = time::timeout(WAIT, listener.accept()).await.unwrap().unwrap();
+        let mut socket = accept_async(stream).await.unwrap();
+        socket.send(Message::Text(handshake.to_owned())).await.unwrap();
+        socket
+    }
+
+    // Next text frame from the client, skipping its heartbeat pings.
+    async fn next_text(socket: &mut ServerSocket) -> String {
+        loop {
+            match time::timeout(WAIT, socket.next()).await.unwrap() {
+                Some(Ok(Message::Text(text))) if text == "2" => continue,
+                Some(Ok(Message::Text(text))) => return text,
+                Some(Ok(_)) => continue,
+                other => panic!("unexpected frame {:?}", other),
+            }
+        }
+    }
+
+    async fn next_event(client: &mut ChatClient) -> ChatEvent {
+        time::timeout(WAIT, client.next()).await.unwrap().unwrap()
+    }
+
+    fn user() -> Value {
+        json!({ "_id": "u1", "name": "tester" })
+    }
+
+    async fn connect(url: &str, policy: ReconnectPolicy) -> ChatClient {
+        ChatClient::connect_as(url, "token", user(), policy).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn handshake_then_init() {
+        let (listener, url) = listen().await;
+        let server = tokio::spawn(async move {
+            let mut socket = accept(&listener, HANDSHAKE).await;
+            next_text(&mut socket).await
+        });
+        let mut client = connect(&url, ReconnectPolicy::never()).await;
+        assert!(matches!(next_event(&mut client).await, ChatEvent::Connected));
+        let init: Value = serde_json::from_str(server.await.unwrap().strip_prefix("42").unwrap()).unwrap();
+        assert_eq!(init, json!(["init", user()]));
+    }
+
+    #[tokio::test]
+    async fn ping_pong() {
+        let (listener, url) = listen().await;
+        let handshake = r#"0{"sid":"test","upgrades":[],"pingInterval":50,"pingTimeout":5000}"#;
+        let server = tokio::spawn(async move {
+            let mut socket = accept(&listener, handshake).await;
+            socket.send(Message::Text("2".to_owned())).await.unwrap();
+            let (mut pinged, mut ponged) = (false, false);
+            while !(pinged && ponged) {
+                match time::timeout(WAIT, socket.next()).await.unwrap() {
+                    Some(Ok(Message::Text(text))) if text == "2" => pinged = true,
+                    Some(Ok(Message::Text(text))) if text == "3" => ponged = true,
+                    Some(Ok(_)) => {}
+                    other => panic!("unexpected frame {:?}", other),
+                }
+            }
+        });
+        let _client = connect(&url, ReconnectPolicy::never()).await;
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn broadcast_becomes_message() {
+        let (listener, url) = listen().await;
+        let server = tokio::spawn(async move {
+            let mut socket = accept(&listener, HANDSHAKE).await;
+            next_text(&mut socket).await;
+            let frame = emit(event::BROADCAST_MESSAGE, &json!({ "message": "hi", "name": "bob", "level": 3 }));
+            socket.send(Message::Text(frame)).await.unwrap();
+            socket
+        });
+        let mut client = connect(&url, ReconnectPolicy::never()).await;
+        assert!(matches!(next_event(&mut client).await, ChatEvent::Connected));
+        match next_event(&mut client).await {
+            ChatEvent::Message(text) => {
+                assert_eq!(text.message, "hi");
+                assert_eq!(text.name, "bob");
+                assert_eq!(text.level, Some(3));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        drop(server.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn send_frames_message() {
+        let (listener, url) = listen().await;
+        let server = tokio::spawn(async move {
+            let mut socket = accept(&listener, HANDSHAKE).await;
+            next_text(&mut socket).await;
+            next_text(&mut socket).await
+        });
+        let mut client = connect(&url, ReconnectPolicy::never()).await;
+        assert!(matches!(next_event(&mut client).await, ChatEvent::Connected));
+        client.send("hello").unwrap();
+        let frame: Value = serde_json::from_str(server.await.unwrap().strip_prefix("42").unwrap()).unwrap();
+        assert_eq!(frame[0], event::SEND_MESSAGE);
+        assert_eq!(frame[1]["message"], "hello");
+        assert_eq!(frame[1]["user_id"], "u1");
+        assert_eq!(frame[1]["name"], "tester");
+        assert_eq!(frame[1]["platform"], app::PLATFORM);
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_close() {
+        let (listener, url) = listen().await;
+        let server = tokio::spawn(async move {
+            let mut socket = accept(&listener, HANDSHAKE).await;
+            next_text(&mut socket).await;
+            socket.close(None).await.unwrap();
+            let mut socket = accept(&listener, HANDSHAKE).await;
+            let init = next_text(&mut socket).await;
+            (socket, init)
+        });
+        let policy = ReconnectPolicy {
+            max_retries: Some(3),
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(10),
+        };
+        let mut client = connect(&url, policy).await;
+        assert!(matches!(next_event(&mut client).await, ChatEvent::Connected));
+        assert!(matches!(
+            next_event(&mut client).await,
+            ChatEvent::Reconnecting { attempt: 1, .. }
+        ));
+        assert!(matches!(next_event(&mut client).await, ChatEvent::Connected));
+        let (_socket, init) = server.await.unwrap();
+        assert!(init.starts_with(r#"42["init""#));
+    }
+
+    #[test]
+    fn reconnect_delay_backs_off() {
+        let policy = ReconnectPolicy {
+            max_retries: Some(4),
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5),
+        };
+        assert_eq!(policy.delay(1), Some(Duration::from_secs(1)));
+        assert_eq!(policy.delay(2), Some(Duration::from_secs(2)));
+        assert_eq!(policy.delay(3), Some(Duration::from_secs(4)));
+        assert_eq!(policy.delay(4), Some(Duration::from_secs(5)));
+        assert_eq!(policy.delay(5), None);
+        assert_eq!(ReconnectPolicy::never().delay(1), None);
+        assert!(ReconnectPolicy::default().delay(1000).is_some());
+    }
+
+    #[test]
+    fn endpoint_uses_socket_io() {
+        assert_eq!(
+            endpoint("https://live.example.com").unwrap(),
+            "wss://live.example.com/socket.io/?EIO=3&transport=websocket"
+        );
+        assert_eq!(
+            endpoint("http://127.0.0.1:8080/socket.io/").unwrap(),
+            "ws://127.0.0.1:8080/socket.io/?EIO=3&transport=websocket"
+        );
+        assert!(endpoint("not a url").is_err());
+    }
+
+    #[test]
+    fn decode_frames() {
+        assert!(matches!(
+            decode(r#"42["receive_notification",{"a":1}]"#),
+            Some(ChatEvent::Notification(_))
+        ));
+        assert!(matches!(
+            decode(r#"42["other",1]"#),
+            Some(ChatEvent::Event { name, .. }) if name == "other"
+        ));
+        assert!(decode("40").is_none());
+        assert!(decode("3").is_none());
+    }
+}
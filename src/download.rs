@@ -0,0 +1,437 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt, BufWriter},
+};
+
+use crate::{
+    api_type::Api,
+    error::Error,
+    manifest::{part_path, sha256, EpRecord, Manifest, PageRecord},
+    responses::{Ep, Page, PictureDownloadResounce, _ComicMetadata},
+    template::PathTemplate,
+    throttle::RateLimiter,
+    transcode::{TranscodeReport, Transcoder},
+    verify::image_kind,
+    ApiResult,
+};
+
+const MANIFEST_SAVE_INTERVAL: usize = 16;
+
+pub type ProgressCallback<'a> = Box<dyn Fn(&Progress) + Send + Sync + 'a>;
+
+pub struct PageContext<'a> {
+    pub comic: &'a _ComicMetadata,
+    pub ep: &'a Ep,
+    pub page: &'a Page,
+    pub index: usize,
+}
+
+impl PageContext<'_> {
+    pub fn ep_order(&self) -> u64 {
+        self.ep.order.unwrap_or_default()
+    }
+
+    pub fn extension(&self) -> &str {
+        Path::new(self.page.media.filename())
+            .extension()
+            .and_then(|v| v.to_str())
+            .unwrap_or("jpg")
+    }
+}
+
+#[derive(Clone, Default)]
+pub enum Layout {
+    // <cid>/<ep_order>/<page_index>.<ext>
+    #[default]
+    Nested,
+    // <cid>/<ep_order>_<page_index>.<ext>
+    Flat,
+    Template(PathTemplate),
+    Custom(Arc<dyn Fn(&PageContext) -> PathBuf + Send + Sync>),
+}
+
+impl Layout {
+    pub fn path(&self, context: &PageContext) -> PathBuf {
+        match self {
+            Self::Nested => PathBuf::from(&context.comic.metadata.id)
+                .join(format!("{:04}", context.ep_order()))
+                .join(format!("{:04}.{}", context.index, context.extension())),
+            Self::Flat => PathBuf::from(&context.comic.metadata.id).join(format!(
+                "{:04}_{:04}.{}",
+                context.ep_order(),
+                context.index,
+                context.extension()
+            )),
+            Self::Template(template) => template.render(context),
+            Self::Custom(layout) => layout(context),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EpSelection {
+    #[default]
+    All,
+    Orders(Vec<u64>),
+    Ids(Vec<String>),
+}
+
+impl EpSelection {
+    pub fn contains(&self, ep: &Ep) -> bool {
+        match self {
+            Self::All => true,
+            Self::Orders(orders) => ep.order.is_some_and(|order| orders.contains(&order)),
+            Self::Ids(ids) => ids.contains(&ep.id),
+        }
+    }
+
+    // Same as `contains`, for an ep already in the manifest.
+    pub fn contains_record(&self, ep: &EpRecord) -> bool {
+        match self {
+            Self::All => true,
+            Self::Orders(orders) => orders.contains(&ep.order),
+            Self::Ids(ids) => ids.contains(&ep.id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Progress {
+    pub bytes: u64,
+    pub pages_done: u64,
+    pub pages_skipped: u64,
+    pub pages_failed: u64,
+    pub pages_total: u64,
+    pub eps_done: u64,
+    pub eps_total: u64,
+}
+
+#[derive(Debug)]
+pub struct FailedPage {
+    pub ep_id: String,
+    pub ep_order: u64,
+    pub index: usize,
+    pub path: PathBuf,
+    pub error: Error,
+}
+
+#[derive(Debug)]
+pub struct DownloadReport {
+    pub cid: String,
+    pub progress: Progress,
+    pub failed: Vec<FailedPage>,
+    pub transcoded: Option<TranscodeReport>,
+}
+
+impl DownloadReport {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+struct PageJob<'a> {
+    ep: usize,
+    ep_id: &'a str,
+    ep_order: u64,
+    index: usize,
+    // relative to the download root
+    path: PathBuf,
+    page: &'a Page,
+}
+
+pub struct ComicDownloader<'a> {
+    api: &'a Api,
+    cid: String,
+    root: PathBuf,
+    layout: Layout,
+    pub(crate) selection: EpSelection,
+    pub(crate) concurrency: usize,
+    retries: u32,
+    retry_delay: Duration,
+    limiter: Option<RateLimiter>,
+    transcoder: Option<Transcoder>,
+    progress: Option<ProgressCallback<'a>>,
+}
+
+impl<'a> ComicDownloader<'a> {
+    pub fn new(api: &'a Api, cid: &str, root: impl Into<PathBuf>) -> Self {
+        Self {
+            api,
+            cid: cid.to_string(),
+            root: root.into(),
+            layout: Layout::default(),
+            selection: EpSelection::default(),
+            concurrency: 4,
+            retries: 3,
+            retry_delay: Duration::from_secs(1),
+            limiter: None,
+            transcoder: None,
+            progress: None,
+        }
+    }
+
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn episodes(mut self, selection: EpSelection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn retries(mut self, retries: u32, delay: Duration) -> Self {
+        self.retries = retries;
+        self.retry_delay = delay;
+        self
+    }
+
+    // Caps this download in bytes per second, on top of `Api::set_bandwidth_limit`.
+    pub fn bandwidth_limit(mut self, rate: Option<u64>) -> Self {
+        self.limiter = rate.map(|rate| RateLimiter::new(Some(rate)));
+        self
+    }
+
+    // Shares a limiter between downloaders; it can be adjusted while they run.
+    pub fn limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    // Re-encodes pages once they are downloaded; already transcoded pages are left alone.
+    pub fn transcode(mut self, transcoder: Transcoder) -> Self {
+        self.transcoder = Some(transcoder);
+        self
+    }
+
+    pub fn on_progress(mut self, callback: impl Fn(&Progress) + Send + Sync + 'a) -> Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    pub fn api(&self) -> &'a Api {
+        self.api
+    }
+
+    pub fn cid(&self) -> &str {
+        &self.cid
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        Manifest::path(&self.root, &self.cid)
+    }
+
+    pub async fn run(&self) -> ApiResult<DownloadReport> {
+        self.run_selected(&self.selection).await
+    }
+
+    pub(crate) async fn run_selected(&self, selection: &EpSelection) -> ApiResult<DownloadReport> {
+        let manifest_path = self.manifest_path();
+        let mut manifest = Manifest::load(&manifest_path)
+            .await?
+            .unwrap_or_else(|| Manifest::new(&self.cid));
+        let comic = self.api.comic_metadata(&self.cid).await?;
+        let eps = if manifest.is_current(&comic) {
+            manifest.eps.iter().map(EpRecord::to_ep).collect()
+        } else {
+            self.api.comic_eps_all(&self.cid).await?
+        };
+        manifest.update(&comic);
+
+        let mut progress = Progress::default();
+        let mut episodes = Vec::new();
+        for ep in eps.into_iter().filter(|ep| selection.contains(ep)) {
+            progress.eps_total += 1;
+            match manifest.ep(&ep.id) {
+                Some(record) if record.is_complete() && record.updated_at == ep.updated_at => {
+                    progress.eps_done += 1;
+                    progress.pages_total += record.pages_total;
+                    progress.pages_done += record.pages_total;
+                    progress.pages_skipped += record.pages_total;
+                }
+                _ => {
+                    let pages = self.api.episode_images(&self.cid, &ep).await?;
+                    let record = manifest.ep_mut(&ep);
+                    record.pages_total = pages.len() as u64;
+                    record.pages.retain(|v| pages.iter().any(|page| page.id == v.id));
+                    episodes.push((ep, pages));
+                }
+            }
+        }
+
+        let mut jobs = Vec::new();
+        for (ep_index, (ep, pages)) in episodes.iter().enumerate() {
+            let record = manifest.ep(&ep.id);
+            for (index, page) in pages.iter().enumerate() {
+                let context = PageContext {
+                    comic: &comic,
+                    ep,
+                    page,
+                    index: index + 1,
+                };
+                let path = self.layout.path(&context);
+                let done = match record.and_then(|v| v.page(&page.id)) {
+                    Some(saved) => fs::metadata(self.root.join(&saved.path))
+                        .await
+                        .is_ok_and(|v| v.len() == saved.size),
+                    None => false,
+                };
+                progress.pages_total += 1;
+                if done {
+                    progress.pages_done += 1;
+                    progress.pages_skipped += 1;
+                    continue;
+                }
+                jobs.push(PageJob {
+                    ep: ep_index,
+                    ep_id: &ep.id,
+                    ep_order: context.ep_order(),
+                    index: index + 1,
+                    path,
+                    page,
+                });
+            }
+        }
+
+        let mut remaining = vec![0; episodes.len()];
+        for job in jobs.iter() {
+            remaining[job.ep] += 1;
+        }
+        progress.eps_done += remaining.iter().filter(|v| **v == 0).count() as u64;
+        self.report(&progress);
+        manifest.save(&manifest_path).await?;
+
+        let mut failed = Vec::new();
+        let mut unsaved = 0;
+        let mut results = stream::iter(jobs)
+            .map(|job| async move {
+                let result = self.fetch(&job).await;
+                (job, result)
+            })
+            .buffer_unordered(self.concurrency);
+        while let Some((job, result)) = results.next().await {
+            match result {
+                Ok((size, sha256)) => {
+                    progress.bytes += size;
+                    progress.pages_done += 1;
+                    manifest.ep_mut(&episodes[job.ep].0).upsert(PageRecord {
+                        id: job.page.id.clone(),
+                        index: job.index,
+                        original_name: job.page.media.filename().to_string(),
+                        path: job.path,
+                        size,
+                        sha256,
+                        transcoded: false,
+                    });
+                    unsaved += 1;
+                }
+                Err(error) => {
+                    progress.pages_failed += 1;
+                    failed.push(FailedPage {
+                        ep_id: job.ep_id.to_string(),
+                        ep_order: job.ep_order,
+                        index: job.index,
+                        path: self.root.join(job.path),
+                        error,
+                    });
+                }
+            }
+            remaining[job.ep] -= 1;
+            if remaining[job.ep] == 0 {
+                progress.eps_done += 1;
+            }
+            if unsaved >= MANIFEST_SAVE_INTERVAL || remaining[job.ep] == 0 {
+                manifest.save(&manifest_path).await?;
+                unsaved = 0;
+            }
+            self.report(&progress);
+        }
+        manifest.save(&manifest_path).await?;
+
+        let transcoded = match self.transcoder.as_ref() {
+            Some(transcoder) => {
+                let report = transcoder.transcode_manifest(&self.root, &mut manifest).await?;
+                manifest.save(&manifest_path).await?;
+                Some(report)
+            }
+            None => None,
+        };
+
+        Ok(DownloadReport {
+            cid: self.cid.clone(),
+            progress,
+            failed,
+            transcoded,
+        })
+    }
+
+    fn report(&self, progress: &Progress) {
+        if let Some(callback) = self.progress.as_ref() {
+            callback(progress);
+        }
+    }
+
+    async fn fetch(&self, job: &PageJob<'_>) -> ApiResult<(u64, String)> {
+        let path = self.root.join(&job.path);
+        let mut attempt = 0;
+        loop {
+            match save(self.api, &job.page.media, &path, self.limiter.as_ref()).await {
+                Ok(size) => return Ok((size, sha256(&fs::read(&path).await?))),
+                Err(err) if attempt >= self.retries => return Err(err),
+                Err(_) => {
+                    attempt += 1;
+                    tokio::time::sleep(self.retry_delay * attempt).await;
+                }
+            }
+        }
+    }
+}
+
+// Downloads into a `.part` file first so an interrupted page never looks complete.
+pub(crate) async fn save(
+    api: &Api,
+    media: &PictureDownloadResounce,
+    path: &Path,
+    limit: Option<&RateLimiter>,
+) -> ApiResult<u64> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let partial = part_path(path);
+    let mut file = BufWriter::new(File::create(&partial).await?);
+    match api.download_image_limited(media, &mut file, limit).await {
+        Ok(bytes) => {
+            drop(file);
+            let mut header = Vec::with_capacity(12);
+            File::open(&partial).await?.take(12).read_to_end(&mut header).await?;
+            if image_kind(&header).is_none() {
+                let _ = fs::remove_file(&partial).await;
+                Err(Error::InvalidImage)?;
+            }
+            fs::rename(&partial, path).await?;
+            Ok(bytes)
+        }
+        Err(err) => {
+            drop(file);
+            let _ = fs::remove_file(&partial).await;
+            Err(err)
+        }
+    }
+}
@@ -11,7 +11,23 @@ pub enum Error {
         detail: String,
     },
     Unlogin,
+    RandomExhausted {
+        wanted: usize,
+        got: usize,
+    },
+    NotFound(String),
     Io(io::Error),
+    Image(image::ImageError),
+    InvalidImage,
+    Download(String),
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    Chat(String),
+    ChatClosed,
+    Zip(zip::result::ZipError),
+    Template(String),
+    Transcode(String),
+    Queue(String),
+    Export(String),
 }
 
 impl From<io::Error> for Error {
@@ -20,6 +36,24 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<image::ImageError> for Error {
+    fn from(value: image::ImageError) -> Self {
+        Self::Image(value)
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+    fn from(value: tokio_tungstenite::tungstenite::Error) -> Self {
+        Self::WebSocket(Box::new(value))
+    }
+}
+
+impl From<zip::result::ZipError> for Error {
+    fn from(value: zip::result::ZipError) -> Self {
+        Self::Zip(value)
+    }
+}
+
 impl From<reqwest::Error> for Error {
     fn from(value: reqwest::Error) -> Self {
         Self::Request(value)
@@ -0,0 +1,98 @@
+use std::fmt::Display;
+
+use futures::{stream, StreamExt};
+use size_utils::Size;
+
+use crate::{download::ComicDownloader, ApiResult};
+
+#[derive(Debug, Clone, Default)]
+pub struct EpEstimate {
+    pub id: String,
+    pub order: u64,
+    pub title: String,
+    pub pages: u64,
+    pub bytes: u64,
+    // pages whose size the server did not report
+    pub unknown: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SizeEstimate {
+    pub cid: String,
+    pub eps: Vec<EpEstimate>,
+}
+
+impl SizeEstimate {
+    pub fn bytes(&self) -> u64 {
+        self.eps.iter().map(|ep| ep.bytes).sum()
+    }
+
+    pub fn pages(&self) -> u64 {
+        self.eps.iter().map(|ep| ep.pages).sum()
+    }
+
+    pub fn unknown(&self) -> u64 {
+        self.eps.iter().map(|ep| ep.unknown).sum()
+    }
+}
+
+impl Display for EpEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04} {} {} pages {}", self.order, self.title, self.pages, Size::from(self.bytes))?;
+        if self.unknown > 0 {
+            write!(f, " ({} unknown)", self.unknown)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for SizeEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for ep in self.eps.iter() {
+            writeln!(f, "{}", ep)?;
+        }
+        write!(f, "{} total {} pages {}", self.cid, self.pages(), Size::from(self.bytes()))?;
+        if self.unknown() > 0 {
+            write!(f, " ({} unknown)", self.unknown())?;
+        }
+        Ok(())
+    }
+}
+
+impl ComicDownloader<'_> {
+    // Sizes the selected eps without downloading them, using the same concurrency as `run`.
+    pub async fn estimate(&self) -> ApiResult<SizeEstimate> {
+        let api = self.api();
+        let mut estimate = SizeEstimate {
+            cid: self.cid().to_string(),
+            eps: Vec::new(),
+        };
+        let mut jobs = Vec::new();
+        for ep in api.comic_eps_all(self.cid()).await? {
+            if !self.selection.contains(&ep) {
+                continue;
+            }
+            let pages = api.episode_images(self.cid(), &ep).await?;
+            estimate.eps.push(EpEstimate {
+                id: ep.id.clone(),
+                order: ep.order.unwrap_or_default(),
+                title: ep.title.clone(),
+                pages: pages.len() as u64,
+                ..Default::default()
+            });
+            let ep_index = estimate.eps.len() - 1;
+            jobs.extend(pages.into_iter().map(|page| (ep_index, page)));
+        }
+
+        let mut sizes = stream::iter(jobs)
+            .map(|(ep_index, page)| async move { (ep_index, api.image_size(&page.media).await) })
+            .buffer_unordered(self.concurrency);
+        while let Some((ep_index, size)) = sizes.next().await {
+            match size {
+                Ok(Some(size)) => estimate.eps[ep_index].bytes += size,
+                Ok(None) | Err(_) => estimate.eps[ep_index].unknown += 1,
+            }
+        }
+        Ok(estimate)
+    }
+}
@@ -0,0 +1,287 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    download::EpSelection,
+    error::Error,
+    manifest::{EpRecord, Manifest, PageRecord},
+    responses::_ComicMetadata,
+    verify::{image_kind, ImageKind},
+};
+
+pub mod cbz;
+pub mod epub;
+pub mod pdf;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Split {
+    #[default]
+    PerComic,
+    PerEpisode,
+}
+
+// A set of eps packaged into one output file.
+pub struct Volume<'a> {
+    pub name: String,
+    pub ep: Option<&'a EpRecord>,
+    pub eps: Vec<&'a EpRecord>,
+}
+
+impl Volume<'_> {
+    pub fn pages(&self) -> impl Iterator<Item = (&EpRecord, &PageRecord)> {
+        self.eps
+            .iter()
+            .flat_map(|ep| ep.pages.iter().map(move |page| (*ep, page)))
+    }
+
+    pub fn page_count(&self) -> u64 {
+        self.eps.iter().map(|ep| ep.pages.len() as u64).sum()
+    }
+
+    pub fn title(&self, comic: &_ComicMetadata) -> String {
+        match self.ep {
+            Some(ep) => format!("{} - {}", comic.metadata.title, ep.title),
+            None => comic.metadata.title.clone(),
+        }
+    }
+}
+
+// Only fully downloaded eps in `episodes` are packaged; no volume at all when there is none.
+pub fn volumes<'a>(manifest: &'a Manifest, episodes: &EpSelection, split: Split) -> Vec<Volume<'a>> {
+    let mut eps: Vec<&EpRecord> = manifest
+        .eps
+        .iter()
+        .filter(|ep| ep.is_complete() && episodes.contains_record(ep))
+        .collect();
+    eps.sort_by_key(|ep| ep.order);
+    if eps.is_empty() {
+        return Vec::new();
+    }
+    match split {
+        Split::PerComic => vec![Volume {
+            name: manifest.cid.clone(),
+            ep: None,
+            eps,
+        }],
+        Split::PerEpisode => eps
+            .into_iter()
+            .map(|ep| Volume {
+                name: format!("{}_{:04}", manifest.cid, ep.order),
+                ep: Some(ep),
+                eps: vec![ep],
+            })
+            .collect(),
+    }
+}
+
+// `image` can only encode AVIF, so the size of an AVIF page is read from its `ispe` box.
+pub(crate) fn image_dimensions(path: &Path) -> Result<(u32, u32), Error> {
+    let mut header = Vec::with_capacity(12);
+    File::open(path)?.take(12).read_to_end(&mut header)?;
+    if image_kind(&header) != Some(ImageKind::Avif) {
+        return Ok(image::image_dimensions(path)?);
+    }
+    avif_dimensions(&std::fs::read(path)?).ok_or(Error::InvalidImage)
+}
+
+// Walks ftyp/meta/iprp/ipco down to the first image spatial extents box.
+fn avif_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let mut data = data;
+    while data.len() >= 8 {
+        let size = u32::from_be_bytes(data[..4].try_into().ok()?) as u64;
+        let kind = &data[4..8];
+        let (header, size) = match size {
+            0 => (8, data.len() as u64),
+            1 => (16, u64::from_be_bytes(data.get(8..16)?.try_into().ok()?)),
+            size => (8, size),
+        };
+        let body = data.get(header..usize::try_from(size).ok()?)?;
+        match kind {
+            // full boxes start with version and flags
+            b"meta" => return avif_dimensions(body.get(4..)?),
+            b"iprp" | b"ipco" => return avif_dimensions(body),
+            b"ispe" => {
+                let width = u32::from_be_bytes(body.get(4..8)?.try_into().ok()?);
+                let height = u32::from_be_bytes(body.get(8..12)?.try_into().ok()?);
+                return Some((width, height));
+            }
+            _ => data = &data[size as usize..],
+        }
+    }
+    None
+}
+
+pub(crate) fn output_path(out_dir: &Path, volume: &Volume, extension: &str) -> PathBuf {
+    out_dir.join(format!("{}.{}", volume.name, extension))
+}
+
+pub(crate) fn page_name(ep: &EpRecord, page: &PageRecord) -> String {
+    let extension = page
+        .path
+        .extension()
+        .and_then(|v| v.to_str())
+        .unwrap_or("jpg");
+    format!("{:04}_{:04}.{}", ep.order, page.index, extension)
+}
+
+pub(crate) fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c if (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{ImageFormat, RgbImage};
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::{
+        manifest::sha256,
+        transcode::{TargetFormat, Transcoder},
+    };
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 48;
+
+    fn comic() -> _ComicMetadata {
+        serde_json::from_value(serde_json::json!({
+            "_id": "c1",
+            "author": "author",
+            "categories": ["category"],
+            "title": "comic",
+            "thumb": { "originalName": "thumb.jpg", "path": "thumb.jpg", "fileServer": "" },
+            "_creator": {
+                "_id": "u1",
+                "characters": [],
+                "exp": 0,
+                "gender": "bot",
+                "level": 1,
+                "name": "uploader",
+            },
+            "tags": [],
+            "updated_at": "2024-02-01T08:00:00.000Z",
+            "created_at": "2024-01-31T12:00:00.000Z",
+            "allowDownload": true,
+            "allowComment": true,
+            "isFavourite": false,
+            "isLiked": false,
+        }))
+        .unwrap()
+    }
+
+    // A root holding one ep of one PNG page, transcoded to `format`.
+    async fn transcoded(name: &str, format: TargetFormat) -> (PathBuf, Manifest) {
+        let root = std::env::temp_dir().join(format!("libpicacg-export-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("c1")).unwrap();
+        let page = RgbImage::from_fn(WIDTH, HEIGHT, |x, y| image::Rgb([x as u8 * 4, y as u8 * 5, 128]));
+        let mut data = Vec::new();
+        page.write_to(&mut Cursor::new(&mut data), ImageFormat::Png).unwrap();
+        std::fs::write(root.join("c1/0001.png"), &data).unwrap();
+
+        let mut manifest = Manifest::new("c1");
+        manifest.eps.push(EpRecord {
+            id: "e1".to_owned(),
+            order: 1,
+            title: "ep".to_owned(),
+            updated_at: None,
+            pages_total: 1,
+            pages: vec![PageRecord {
+                id: "p1".to_owned(),
+                index: 1,
+                original_name: "0001.png".to_owned(),
+                path: PathBuf::from("c1/0001.png"),
+                size: data.len() as u64,
+                sha256: sha256(&data),
+                transcoded: false,
+            }],
+        });
+        let report = Transcoder::new(format)
+            .unwrap()
+            .transcode_manifest(&root, &mut manifest)
+            .await
+            .unwrap();
+        assert_eq!(report.pages, 1);
+        assert!(report.failed.is_empty());
+        (root, manifest)
+    }
+
+    fn entries(path: &Path) -> Vec<String> {
+        let archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        archive.file_names().map(str::to_owned).collect()
+    }
+
+    #[tokio::test]
+    async fn transcoded_pages_round_trip_through_exporters() {
+        let (root, manifest) = transcoded("jpeg", TargetFormat::Jpeg).await;
+        let page = &manifest.eps[0].pages[0];
+        assert_eq!(page.path, PathBuf::from("c1/0001.jpg"));
+        let out = root.join("out");
+        let comic = comic();
+
+        let cbz = cbz::write_cbz(&root, &manifest, &EpSelection::All, &comic, Split::PerComic, &out).unwrap();
+        assert!(entries(&cbz[0]).contains(&"0001_0001.jpg".to_owned()));
+
+        let epub = epub::write_epub(&root, &manifest, &EpSelection::All, &comic, None, &out).unwrap();
+        let mut archive = ZipArchive::new(File::open(&epub).unwrap()).unwrap();
+        let mut xhtml = String::new();
+        archive.by_name("OEBPS/pages/0001_0001.xhtml").unwrap().read_to_string(&mut xhtml).unwrap();
+        assert!(xhtml.contains(&WIDTH.to_string()) && xhtml.contains(&HEIGHT.to_string()));
+
+        let pdf = pdf::write_pdf(&root, &manifest, &EpSelection::All, &comic, Split::PerComic, &out).unwrap();
+        let pdf = std::fs::read(&pdf[0]).unwrap();
+        let pdf = String::from_utf8_lossy(&pdf);
+        assert!(pdf.contains(&format!("/Width {} /Height {}", WIDTH, HEIGHT)));
+        assert!(pdf.contains("/CreationDate (D:20240131120000Z)"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn exporters_only_take_selected_eps() {
+        let (root, manifest) = transcoded("selection", TargetFormat::Jpeg).await;
+        let out = root.join("out");
+        let cbz = cbz::write_cbz(&root, &manifest, &EpSelection::Orders(vec![2]), &comic(), Split::PerEpisode, &out);
+        assert!(cbz.unwrap().is_empty());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(feature = "avif")]
+    #[tokio::test]
+    async fn avif_pages_export_to_epub_but_not_pdf() {
+        let (root, manifest) = transcoded("avif", TargetFormat::Avif).await;
+        let page = &manifest.eps[0].pages[0];
+        assert_eq!(image_dimensions(&root.join(&page.path)).unwrap(), (WIDTH, HEIGHT));
+        let out = root.join("out");
+        let comic = comic();
+
+        let epub = epub::write_epub(&root, &manifest, &EpSelection::All, &comic, None, &out).unwrap();
+        assert!(entries(&epub).contains(&"OEBPS/images/0001_0001.avif".to_owned()));
+        let pdf = pdf::write_pdf(&root, &manifest, &EpSelection::All, &comic, Split::PerComic, &out);
+        assert!(matches!(pdf, Err(Error::Export(_))));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn avif_dimensions_rejects_truncated_boxes() {
+        assert_eq!(avif_dimensions(b"\0\0\0\x20ftypavif"), None);
+        assert_eq!(avif_dimensions(&[]), None);
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{download::EpSelection, error::Error, manifest::Manifest, responses::_ComicMetadata};
+
+use super::{output_path, page_name, volumes, xml_escape, Split, Volume};
+
+pub const EXTENSION: &'static str = "cbz";
+pub const COMIC_INFO: &'static str = "ComicInfo.xml";
+
+pub fn comic_info(comic: &_ComicMetadata, volume: &Volume) -> String {
+    let mut fields: Vec<(&str, String)> = vec![
+        ("Title", volume.title(comic)),
+        ("Series", comic.metadata.title.clone()),
+    ];
+    if let Some(ep) = volume.ep {
+        fields.push(("Number", ep.order.to_string()));
+    }
+    if comic.metadata.finished {
+        fields.push(("Count", comic.metadata.eps_count.to_string()));
+    }
+    if let Some(description) = comic.description.as_ref() {
+        fields.push(("Summary", description.clone()));
+    }
+    fields.push((
+        "Notes",
+        format!(
+            "Status: {}",
+            if comic.metadata.finished { "finished" } else { "ongoing" }
+        ),
+    ));
+    fields.push(("Writer", comic.metadata.author.clone()));
+    if let Some(team) = comic.chinese_team.as_ref() {
+        fields.push(("Translator", team.clone()));
+    }
+    fields.push(("Genre", comic.metadata.categories.join(",")));
+    fields.push(("Tags", comic.tags.join(",")));
+    fields.push(("PageCount", volume.page_count().to_string()));
+    fields.push(("LanguageISO", "zh".to_owned()));
+    fields.push(("ScanInformation", comic.creator.name.clone()));
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<ComicInfo xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\">\n",
+    );
+    for (name, value) in fields.into_iter().filter(|(_, v)| !v.is_empty()) {
+        xml.push_str(&format!("  <{0}>{1}</{0}>\n", name, xml_escape(&value)));
+    }
+    xml.push_str("</ComicInfo>\n");
+    xml
+}
+
+pub fn write_cbz(
+    root: &Path,
+    manifest: &Manifest,
+    episodes: &EpSelection,
+    comic: &_ComicMetadata,
+    split: Split,
+    out_dir: &Path,
+) -> Result<Vec<PathBuf>, Error> {
+    std::fs::create_dir_all(out_dir)?;
+    let mut outputs = Vec::new();
+    for volume in volumes(manifest, episodes, split) {
+        let path = output_path(out_dir, &volume, EXTENSION);
+        let mut archive = ZipWriter::new(BufWriter::new(File::create(&path)?));
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        for (ep, page) in volume.pages() {
+            archive.start_file(page_name(ep, page), stored)?;
+            io::copy(&mut File::open(root.join(&page.path))?, &mut archive)?;
+        }
+        archive.start_file(
+            COMIC_INFO,
+            SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
+        )?;
+        archive.write_all(comic_info(comic, &volume).as_bytes())?;
+        archive.finish()?.flush()?;
+        outputs.push(path);
+    }
+    Ok(outputs)
+}
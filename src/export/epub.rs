@@ -0,0 +1,259 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{api_type::Api, download::EpSelection, error::Error, manifest::Manifest, responses::_ComicMetadata};
+
+use super::{image_dimensions, output_path, page_name, volumes, xml_escape, Split};
+
+pub const EXTENSION: &'static str = "epub";
+
+const CONTAINER: &'static str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+struct Item {
+    id: String,
+    href: String,
+    media_type: &'static str,
+    properties: Option<&'static str>,
+}
+
+pub fn media_type(name: &str) -> &'static str {
+    match Path::new(name)
+        .extension()
+        .and_then(|v| v.to_str())
+        .map(|v| v.to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("gif") => "image/gif",
+        _ => "image/jpeg",
+    }
+}
+
+pub async fn cover(api: &Api, comic: &_ComicMetadata) -> Result<Vec<u8>, Error> {
+    api.download_image(&comic.metadata.thumb).await
+}
+
+fn page_xhtml(title: &str, image: &str, width: u32, height: u32) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{title}</title>
+  <meta name="viewport" content="width={width}, height={height}"/>
+  <style>html, body {{ margin: 0; padding: 0; }} img {{ display: block; width: {width}px; height: {height}px; }}</style>
+</head>
+<body>
+  <img src="{image}" alt=""/>
+</body>
+</html>
+"#,
+        title = xml_escape(title),
+        image = xml_escape(image),
+        width = width,
+        height = height,
+    )
+}
+
+fn nav_xhtml(title: &str, chapters: &[(String, String)]) -> String {
+    let mut entries = String::new();
+    for (title, href) in chapters {
+        entries.push_str(&format!(
+            "      <li><a href=\"{}\">{}</a></li>\n",
+            xml_escape(href),
+            xml_escape(title)
+        ));
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{title}</title>
+</head>
+<body>
+  <nav epub:type="toc" id="toc">
+    <h1>{title}</h1>
+    <ol>
+{entries}    </ol>
+  </nav>
+</body>
+</html>
+"#,
+        title = xml_escape(title),
+        entries = entries,
+    )
+}
+
+fn opf(comic: &_ComicMetadata, items: &[Item], spine: &[String]) -> String {
+    let mut metadata = vec![
+        format!(
+            "<dc:identifier id=\"uid\">urn:picacg:{}</dc:identifier>",
+            xml_escape(&comic.metadata.id)
+        ),
+        format!("<dc:title>{}</dc:title>", xml_escape(&comic.metadata.title)),
+        "<dc:language>zh</dc:language>".to_owned(),
+        format!("<dc:creator id=\"author\">{}</dc:creator>", xml_escape(&comic.metadata.author)),
+        "<meta refines=\"#author\" property=\"role\" scheme=\"marc:relators\">aut</meta>".to_owned(),
+    ];
+    if let Some(team) = comic.chinese_team.as_ref() {
+        metadata.push(format!("<dc:contributor id=\"translator\">{}</dc:contributor>", xml_escape(team)));
+        metadata.push("<meta refines=\"#translator\" property=\"role\" scheme=\"marc:relators\">trl</meta>".to_owned());
+    }
+    metadata.push(format!("<dc:publisher>{}</dc:publisher>", xml_escape(&comic.creator.name)));
+    if let Some(description) = comic.description.as_ref() {
+        metadata.push(format!("<dc:description>{}</dc:description>", xml_escape(description)));
+    }
+    for subject in comic.tags.iter().chain(comic.metadata.categories.iter()) {
+        metadata.push(format!("<dc:subject>{}</dc:subject>", xml_escape(subject)));
+    }
+    metadata.push(format!(
+        "<meta property=\"dcterms:modified\">{}</meta>",
+        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+    ));
+    metadata.push("<meta property=\"rendition:layout\">pre-paginated</meta>".to_owned());
+    metadata.push("<meta property=\"rendition:orientation\">auto</meta>".to_owned());
+    metadata.push("<meta property=\"rendition:spread\">none</meta>".to_owned());
+
+    let manifest: Vec<String> = items
+        .iter()
+        .map(|item| {
+            format!(
+                "<item id=\"{}\" href=\"{}\" media-type=\"{}\"{}/>",
+                item.id,
+                xml_escape(&item.href),
+                item.media_type,
+                item.properties
+                    .map(|v| format!(" properties=\"{}\"", v))
+                    .unwrap_or_default()
+            )
+        })
+        .collect();
+    let spine: Vec<String> = spine.iter().map(|id| format!("<itemref idref=\"{}\"/>", id)).collect();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid" prefix="rendition: http://www.idpf.org/vocab/rendition/#">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    {}
+  </metadata>
+  <manifest>
+    {}
+  </manifest>
+  <spine>
+    {}
+  </spine>
+</package>
+"#,
+        metadata.join("\n    "),
+        manifest.join("\n    "),
+        spine.join("\n    "),
+    )
+}
+
+pub fn write_epub(
+    root: &Path,
+    manifest: &Manifest,
+    episodes: &EpSelection,
+    comic: &_ComicMetadata,
+    cover: Option<&[u8]>,
+    out_dir: &Path,
+) -> Result<PathBuf, Error> {
+    std::fs::create_dir_all(out_dir)?;
+    let volume = volumes(manifest, episodes, Split::PerComic)
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::NotFound(format!("{} has no complete ep", manifest.cid)))?;
+    let path = output_path(out_dir, &volume, EXTENSION);
+    let mut archive = ZipWriter::new(BufWriter::new(File::create(&path)?));
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // the mimetype entry must come first and stay uncompressed
+    archive.start_file("mimetype", stored)?;
+    archive.write_all(b"application/epub+zip")?;
+    archive.start_file("META-INF/container.xml", deflated)?;
+    archive.write_all(CONTAINER.as_bytes())?;
+
+    let mut items = vec![Item {
+        id: "nav".to_owned(),
+        href: "nav.xhtml".to_owned(),
+        media_type: "application/xhtml+xml",
+        properties: Some("nav"),
+    }];
+    let mut spine = Vec::new();
+    let mut chapters = Vec::new();
+    let mut chapter = None;
+
+    if let Some(cover) = cover {
+        let format = image::guess_format(cover)?;
+        let extension = format.extensions_str().first().copied().unwrap_or("jpg");
+        let name = format!("cover.{}", extension);
+        let (width, height) = image::ImageReader::with_format(io::Cursor::new(cover), format).into_dimensions()?;
+        archive.start_file(format!("OEBPS/images/{}", name), stored)?;
+        archive.write_all(cover)?;
+        archive.start_file("OEBPS/pages/cover.xhtml", deflated)?;
+        archive.write_all(page_xhtml(&comic.metadata.title, &format!("../images/{}", name), width, height).as_bytes())?;
+        items.push(Item {
+            id: "cover-image".to_owned(),
+            href: format!("images/{}", name),
+            media_type: media_type(&name),
+            properties: Some("cover-image"),
+        });
+        items.push(Item {
+            id: "cover".to_owned(),
+            href: "pages/cover.xhtml".to_owned(),
+            media_type: "application/xhtml+xml",
+            properties: None,
+        });
+        spine.push("cover".to_owned());
+    }
+
+    for (number, (ep, page)) in volume.pages().enumerate() {
+        let name = page_name(ep, page);
+        let stem = name.rsplit_once('.').map(|(v, _)| v).unwrap_or(&name).to_string();
+        let source = root.join(&page.path);
+        let (width, height) = image_dimensions(&source)?;
+        archive.start_file(format!("OEBPS/images/{}", name), stored)?;
+        io::copy(&mut File::open(&source)?, &mut archive)?;
+        archive.start_file(format!("OEBPS/pages/{}.xhtml", stem), deflated)?;
+        archive.write_all(page_xhtml(&ep.title, &format!("../images/{}", name), width, height).as_bytes())?;
+        items.push(Item {
+            id: format!("img{}", number),
+            href: format!("images/{}", name),
+            media_type: media_type(&name),
+            properties: None,
+        });
+        items.push(Item {
+            id: format!("page{}", number),
+            href: format!("pages/{}.xhtml", stem),
+            media_type: "application/xhtml+xml",
+            properties: None,
+        });
+        spine.push(format!("page{}", number));
+        if chapter != Some(ep.id.as_str()) {
+            chapter = Some(ep.id.as_str());
+            chapters.push((ep.title.clone(), format!("pages/{}.xhtml", stem)));
+        }
+    }
+
+    archive.start_file("OEBPS/nav.xhtml", deflated)?;
+    archive.write_all(nav_xhtml(&comic.metadata.title, &chapters).as_bytes())?;
+    archive.start_file("OEBPS/content.opf", deflated)?;
+    archive.write_all(opf(comic, &items, &spine).as_bytes())?;
+    archive.finish()?.flush()?;
+    Ok(path)
+}
@@ -0,0 +1,279 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{write::ZlibEncoder, Compression};
+use image::ImageFormat;
+
+use crate::{
+    download::EpSelection,
+    error::Error,
+    manifest::Manifest,
+    responses::_ComicMetadata,
+    verify::{image_kind, ImageKind},
+};
+
+use super::{output_path, volumes, Split};
+
+pub const EXTENSION: &'static str = "pdf";
+
+struct PdfWriter<W: Write> {
+    out: W,
+    offset: u64,
+    offsets: Vec<u64>,
+}
+
+impl<W: Write> PdfWriter<W> {
+    fn new(out: W) -> io::Result<Self> {
+        let mut writer = Self {
+            out,
+            offset: 0,
+            offsets: Vec::new(),
+        };
+        writer.write(b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n")?;
+        Ok(writer)
+    }
+
+    fn reserve(&mut self) -> usize {
+        self.offsets.push(0);
+        self.offsets.len()
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.out.write_all(data)?;
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+
+    fn begin(&mut self, id: usize) -> io::Result<()> {
+        self.offsets[id - 1] = self.offset;
+        self.write(format!("{} 0 obj\n", id).as_bytes())
+    }
+
+    fn object(&mut self, id: usize, body: &str) -> io::Result<()> {
+        self.begin(id)?;
+        self.write(body.as_bytes())?;
+        self.write(b"\nendobj\n")
+    }
+
+    fn stream(&mut self, id: usize, dict: &str, data: &[u8]) -> io::Result<()> {
+        self.begin(id)?;
+        self.write(format!("<< {} /Length {} >>\nstream\n", dict, data.len()).as_bytes())?;
+        self.write(data)?;
+        self.write(b"\nendstream\nendobj\n")
+    }
+
+    fn finish(mut self, root: usize, info: usize) -> io::Result<W> {
+        let xref = self.offset;
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in self.offsets.iter() {
+            table.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        table.push_str(&format!(
+            "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            root,
+            info,
+            xref
+        ));
+        self.write(table.as_bytes())?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+struct PdfImage {
+    width: u32,
+    height: u32,
+    dict: String,
+    data: Vec<u8>,
+}
+
+// Width, height and component count from the SOF segment of a baseline or progressive JPEG.
+fn jpeg_header(data: &[u8]) -> Option<(u32, u32, u8)> {
+    let mut position = 2;
+    while position + 4 <= data.len() {
+        if data[position] != 0xFF {
+            return None;
+        }
+        let marker = data[position + 1];
+        let length = u16::from_be_bytes([data[position + 2], data[position + 3]]) as usize;
+        if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            let segment = data.get(position + 4..position + 10)?;
+            let height = u16::from_be_bytes([segment[1], segment[2]]) as u32;
+            let width = u16::from_be_bytes([segment[3], segment[4]]) as u32;
+            return Some((width, height, segment[5]));
+        }
+        position += 2 + length;
+    }
+    None
+}
+
+fn load_image(path: &Path) -> Result<PdfImage, Error> {
+    let data = std::fs::read(path)?;
+    if image_kind(&data) == Some(ImageKind::Avif) {
+        Err(Error::Export(format!("{} is AVIF, which cannot be embedded in a PDF", path.to_string_lossy())))?;
+    }
+    let format = image::guess_format(&data)?;
+    if format == ImageFormat::Jpeg {
+        let color_space = match jpeg_header(&data) {
+            Some((width, height, 1)) => Some((width, height, "/DeviceGray")),
+            Some((width, height, 3)) => Some((width, height, "/DeviceRGB")),
+            _ => None,
+        };
+        if let Some((width, height, color_space)) = color_space {
+            return Ok(PdfImage {
+                width,
+                height,
+                dict: format!(
+                    "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {} /BitsPerComponent 8 /Filter /DCTDecode",
+                    width, height, color_space
+                ),
+                data,
+            });
+        }
+    }
+    let image = image::load_from_memory_with_format(&data, format)?.into_rgb8();
+    let (width, height) = image.dimensions();
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(image.as_raw())?;
+    Ok(PdfImage {
+        width,
+        height,
+        dict: format!(
+            "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode",
+            width, height
+        ),
+        data: encoder.finish()?,
+    })
+}
+
+fn text(value: &str) -> String {
+    let mut encoded = String::from("<FEFF");
+    for unit in value.encode_utf16() {
+        encoded.push_str(&format!("{:04X}", unit));
+    }
+    encoded.push('>');
+    encoded
+}
+
+// PDF date string, e.g. `D:20240131120000Z`
+fn date(value: &str) -> Option<String> {
+    let date = chrono::DateTime::parse_from_rfc3339(value).ok()?;
+    Some(format!("(D:{}Z)", date.with_timezone(&chrono::Utc).format("%Y%m%d%H%M%S")))
+}
+
+pub fn write_pdf(
+    root: &Path,
+    manifest: &Manifest,
+    episodes: &EpSelection,
+    comic: &_ComicMetadata,
+    split: Split,
+    out_dir: &Path,
+) -> Result<Vec<PathBuf>, Error> {
+    std::fs::create_dir_all(out_dir)?;
+    let mut outputs = Vec::new();
+    for volume in volumes(manifest, episodes, split) {
+        let path = output_path(out_dir, &volume, EXTENSION);
+        let mut pdf = PdfWriter::new(BufWriter::new(File::create(&path)?))?;
+        let catalog = pdf.reserve();
+        let pages = pdf.reserve();
+        let outlines = pdf.reserve();
+        let info = pdf.reserve();
+
+        let mut kids = Vec::new();
+        let mut chapters: Vec<(String, usize)> = Vec::new();
+        for (ep, page) in volume.pages() {
+            let image = load_image(&root.join(&page.path))?;
+            let page_id = pdf.reserve();
+            let content_id = pdf.reserve();
+            let image_id = pdf.reserve();
+            pdf.stream(image_id, &image.dict, &image.data)?;
+            let content = format!("q {} 0 0 {} 0 0 cm /Im0 Do Q", image.width, image.height);
+            pdf.stream(content_id, "", content.as_bytes())?;
+            pdf.object(
+                page_id,
+                &format!(
+                    "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] /Resources << /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>",
+                    pages, image.width, image.height, image_id, content_id
+                ),
+            )?;
+            if chapters.last().map(|(id, _)| id != &ep.id).unwrap_or(true) {
+                chapters.push((ep.id.clone(), page_id));
+            }
+            kids.push(page_id);
+        }
+
+        let items: Vec<usize> = chapters.iter().map(|_| pdf.reserve()).collect();
+        for (index, (ep_id, page_id)) in chapters.iter().enumerate() {
+            let title = manifest.ep(ep_id).map(|ep| ep.title.as_str()).unwrap_or_default();
+            let mut body = format!(
+                "<< /Title {} /Parent {} 0 R /Dest [{} 0 R /Fit]",
+                text(title),
+                outlines,
+                page_id
+            );
+            if index > 0 {
+                body.push_str(&format!(" /Prev {} 0 R", items[index - 1]));
+            }
+            if let Some(next) = items.get(index + 1) {
+                body.push_str(&format!(" /Next {} 0 R", next));
+            }
+            body.push_str(" >>");
+            pdf.object(items[index], &body)?;
+        }
+        match (items.first(), items.last()) {
+            (Some(first), Some(last)) => pdf.object(
+                outlines,
+                &format!(
+                    "<< /Type /Outlines /First {} 0 R /Last {} 0 R /Count {} >>",
+                    first,
+                    last,
+                    items.len()
+                ),
+            )?,
+            _ => pdf.object(outlines, "<< /Type /Outlines /Count 0 >>")?,
+        }
+
+        let kids: Vec<String> = kids.iter().map(|id| format!("{} 0 R", id)).collect();
+        pdf.object(
+            pages,
+            &format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), kids.len()),
+        )?;
+
+        let mut keywords = comic.tags.clone();
+        keywords.extend(comic.metadata.categories.iter().cloned());
+        let mut body = format!(
+            "<< /Title {} /Author {} /Keywords {} /Uploader {} /Creator {} /Producer {}",
+            text(&volume.title(comic)),
+            text(&comic.metadata.author),
+            text(&keywords.join(", ")),
+            text(&comic.creator.name),
+            text("libpicacg"),
+            text("libpicacg")
+        );
+        if let Some(created_at) = date(&comic.created_at) {
+            body.push_str(&format!(" /CreationDate {}", created_at));
+        }
+        if let Some(updated_at) = date(&comic.updated_at) {
+            body.push_str(&format!(" /ModDate {}", updated_at));
+        }
+        if let Some(description) = comic.description.as_ref() {
+            body.push_str(&format!(" /Subject {}", text(description)));
+        }
+        body.push_str(" >>");
+        pdf.object(info, &body)?;
+        pdf.object(
+            catalog,
+            &format!(
+                "<< /Type /Catalog /Pages {} 0 R /Outlines {} 0 R /PageMode /UseOutlines >>",
+                pages, outlines
+            ),
+        )?;
+        pdf.finish(catalog, info)?;
+        outputs.push(path);
+    }
+    Ok(outputs)
+}
@@ -1,17 +1,23 @@
 #![allow(unused)]
 use std::{
-    fmt::Debug,
+    collections::HashSet,
+    fmt::{Debug, Display},
+    ops::DerefMut,
     str::FromStr,
     time, path::PathBuf,
 };
 
 use chrono::NaiveDateTime;
+use futures::{stream, Future, Stream, StreamExt};
 use hex::ToHex;
 use hmac::{digest::InvalidLength, Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use rand::Rng;
 use reqwest::header::HeaderMap;
 
-use serde::{Deserialize, Deserializer, Serializer};
+use base64::Engine;
+use image::{imageops::FilterType, ImageFormat};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serializer};
 use serde_json::Value;
 use sha2::Sha256;
 
@@ -21,8 +27,11 @@ use crate::{
     r#type::app,
     responses::{
         Docs, GameDownloadInfo, PictureDownloadResounce, GameDownloadInfoP2p, GameDownloadInfoDrive, GameDownloadInfoS3,
+        Categorie, Comic, LikeAction, Page, SearchRow, _ComicMetadata,
     },
-    Header, Quality, Response, Sort,
+    error::Error,
+    ApiResult, ComicFilter, GamePlatform, Header, Mirror, MirrorKind, MirrorProbe, Quality, RankingKind, RankingPeriod,
+    Response, SearchQuery, Sort,
 };
 
 pub fn num_correct_deserializer<'de, D>(de: D) -> Result<u64, D::Error>
@@ -173,6 +182,175 @@ impl ToString for Sort {
     }
 }
 
+impl RankingPeriod {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::H24 => "H24",
+            Self::D7 => "D7",
+            Self::D30 => "D30",
+        }
+    }
+}
+
+impl FromStr for RankingPeriod {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "h24" | "day" => Self::H24,
+            "d7" | "week" => Self::D7,
+            "d30" | "month" => Self::D30,
+            _ => Err(())?,
+        })
+    }
+}
+
+impl Display for RankingPeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl RankingKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Views => "VC",
+        }
+    }
+}
+
+impl FromStr for RankingKind {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "views" | "vc" => Self::Views,
+            _ => Err(())?,
+        })
+    }
+}
+
+impl Display for RankingKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl ComicFilter {
+    pub fn key(&self) -> &str {
+        match self {
+            Self::Category(_) => "c",
+            Self::Tag(_) => "t",
+            Self::Author(_) => "a",
+            Self::Creator(_) => "ca",
+            Self::ChineseTeam(_) => "ct",
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        match self {
+            Self::Category(v)
+            | Self::Tag(v)
+            | Self::Author(v)
+            | Self::Creator(v)
+            | Self::ChineseTeam(v) => v,
+        }
+    }
+
+    pub fn encoded_value(&self) -> String {
+        utf8_percent_encode(self.value(), NON_ALPHANUMERIC).to_string()
+    }
+}
+
+impl SearchQuery {
+    pub fn new(keyword: &str) -> Self {
+        Self {
+            keyword: keyword.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn category(mut self, category: &str) -> Self {
+        self.categories.push(category.to_string());
+        self
+    }
+
+    pub fn categories<I, S>(mut self, categories: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.categories.extend(categories.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn sort(mut self, sort: Sort) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn included_categories(&self) -> &[String] {
+        &self.categories
+    }
+
+    pub fn sorting(&self) -> Sort {
+        self.sort
+    }
+
+    pub(crate) fn payload(&self) -> Value {
+        let mut payload = serde_json::json!({
+            "keyword": self.keyword,
+            "sort": self.sort.as_str(),
+        });
+        if !self.categories.is_empty() {
+            payload["categories"] = serde_json::json!(self.categories);
+        }
+        payload
+    }
+}
+
+impl Comic {
+    pub fn author_filter(&self) -> ComicFilter {
+        ComicFilter::Author(self.author.clone())
+    }
+
+    pub fn category_filters(&self) -> Vec<ComicFilter> {
+        self.categories.iter().cloned().map(ComicFilter::Category).collect()
+    }
+}
+
+impl SearchRow {
+    pub fn tag_filters(&self) -> Vec<ComicFilter> {
+        self.tags.iter().cloned().map(ComicFilter::Tag).collect()
+    }
+
+    pub fn chinese_team_filter(&self) -> Option<ComicFilter> {
+        self.chinese_team.clone().map(ComicFilter::ChineseTeam)
+    }
+}
+
+impl _ComicMetadata {
+    pub fn tag_filters(&self) -> Vec<ComicFilter> {
+        self.tags.iter().cloned().map(ComicFilter::Tag).collect()
+    }
+
+    pub fn creator_filter(&self) -> ComicFilter {
+        ComicFilter::Creator(self.creator.id.clone())
+    }
+
+    pub fn chinese_team_filter(&self) -> Option<ComicFilter> {
+        self.chinese_team.clone().map(ComicFilter::ChineseTeam)
+    }
+}
+
+impl Categorie {
+    pub fn filter(&self) -> ComicFilter {
+        ComicFilter::Category(self.title.clone())
+    }
+}
+
 type HmacSha256 = Hmac<Sha256>;
 
 fn hmac_sha256(data: &[u8], key: &[u8]) -> Result<[u8; 32], InvalidLength> {
@@ -308,6 +486,40 @@ impl<T: Debug> Response<T> {
     }
 }
 
+impl<T: Debug + DeserializeOwned> Response<T> {
+    pub(crate) fn into_result(self) -> Result<T, Error> {
+        if self.is_error() {
+            return Err(self.into());
+        }
+        match self.data {
+            Some(data) => Ok(data),
+            None => Ok(T::deserialize(Value::Null)?),
+        }
+    }
+}
+
+pub const AVATAR_SIZE: u32 = 200;
+pub const AVATAR_MAX_BYTES: usize = 8 * 1024 * 1024;
+
+pub fn avatar_encode(image: &[u8]) -> Result<String, Error> {
+    if image.len() > AVATAR_MAX_BYTES {
+        Err(Error::InvalidImage)?;
+    }
+    match image::guess_format(image) {
+        Ok(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP) => {}
+        _ => Err(Error::InvalidImage)?,
+    }
+    let avatar = image::load_from_memory(image)?
+        .resize_to_fill(AVATAR_SIZE, AVATAR_SIZE, FilterType::Lanczos3)
+        .into_rgb8();
+    let mut buffer = std::io::Cursor::new(Vec::new());
+    avatar.write_to(&mut buffer, ImageFormat::Jpeg)?;
+    Ok(format!(
+        "data:image/jpeg;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(buffer.into_inner())
+    ))
+}
+
 impl PictureDownloadResounce {
     pub fn filename(&self) -> &str {
         &self.original_name
@@ -321,12 +533,118 @@ impl PictureDownloadResounce {
         &self.path
     }
 
-    pub fn download_url(&self) -> reqwest::Url {
-        let resource_path = PathBuf::from_str(self.resource_path()).unwrap();
-        format!("{}/static/{}", self.server(), resource_path.file_name().unwrap().to_str().unwrap())
-        //format!("{}/static/{}", self.server(), self.resource_path())
-            .parse()
+    pub fn host(&self) -> String {
+        api::host::PIC
+            .read()
             .unwrap()
+            .clone()
+            .unwrap_or_else(|| self.server().to_string())
+    }
+
+    pub fn download_urls(&self) -> Vec<reqwest::Url> {
+        let host = self.host();
+        let host = host.trim_end_matches('/');
+        let path = self.resource_path().trim_start_matches('/');
+        let name = PathBuf::from_str(path)
+            .ok()
+            .and_then(|v| v.file_name().and_then(|v| v.to_str()).map(|v| v.to_string()))
+            .unwrap_or_else(|| path.to_string());
+        let mut urls = vec![format!("{}/static/{}", host, name)];
+        if path != name {
+            urls.push(format!("{}/static/{}", host, path));
+        }
+        urls.into_iter().filter_map(|v| v.parse().ok()).collect()
+    }
+
+    pub fn download_url(&self) -> reqwest::Url {
+        self.download_urls().into_iter().next().unwrap()
+    }
+}
+
+pub(crate) fn paginate<T, P, F, Fut>(fetch: F) -> impl Stream<Item = ApiResult<T>>
+where
+    P: DerefMut<Target = Docs<T>>,
+    F: Fn(u64) -> Fut,
+    Fut: Future<Output = ApiResult<P>>,
+{
+    stream::unfold((fetch, Some(1)), |(fetch, page)| async move {
+        let page = page?;
+        Some(match fetch(page).await {
+            Ok(mut docs) => {
+                let next = docs.has_next().then(|| docs.next());
+                let items: Vec<T> = std::mem::take(&mut docs);
+                (items.into_iter().map(Ok).collect::<Vec<_>>(), (fetch, next))
+            }
+            Err(err) => (vec![Err(err)], (fetch, None)),
+        })
+    })
+    .flat_map(stream::iter)
+}
+
+impl Page {
+    pub fn download_url(&self) -> reqwest::Url {
+        self.media.download_url()
+    }
+}
+
+impl GameDownloadInfo {
+    // Every non-empty link, direct downloads first.
+    pub fn mirrors(&self) -> Vec<Mirror> {
+        let mut mirrors: Vec<Mirror> = self
+            .node
+            .iter()
+            .map(|url| (MirrorKind::Node, url))
+            .chain([
+                (MirrorKind::S3Sg, &self.s3.sg),
+                (MirrorKind::S3Sg2, &self.s3.sg2),
+                (MirrorKind::S3Us, &self.s3.us),
+                (MirrorKind::OneDrive, &self.drive.onedrive),
+                (MirrorKind::BitTorrent, &self.p2p.bt),
+            ])
+            .filter(|(_, url)| !url.trim().is_empty())
+            .map(|(kind, url)| Mirror {
+                kind,
+                url: url.trim().to_string(),
+            })
+            .collect();
+        // the same link can be listed under several kinds, not always next to each other
+        let mut seen = HashSet::new();
+        mirrors.retain(|mirror| seen.insert(mirror.url.clone()));
+        mirrors
+    }
+}
+
+impl MirrorKind {
+    // Whether the link serves the file itself rather than a share page or a torrent.
+    pub fn is_direct(&self) -> bool {
+        matches!(self, Self::Node | Self::S3Sg | Self::S3Sg2 | Self::S3Us)
+    }
+}
+
+impl Mirror {
+    pub fn file_name(&self) -> Option<String> {
+        reqwest::Url::parse(&self.url)
+            .ok()?
+            .path_segments()?
+            .rev()
+            .find(|v| !v.is_empty())
+            .map(|v| {
+                percent_encoding::percent_decode_str(v)
+                    .decode_utf8_lossy()
+                    .to_string()
+            })
+    }
+}
+
+impl MirrorProbe {
+    pub fn is_available(&self) -> bool {
+        self.error.is_none() && self.status.is_some_and(|v| (200..400).contains(&v))
+    }
+}
+
+impl LikeAction {
+    pub fn is_liked(&self) -> bool {
+        self.action == "like"
     }
 }
 
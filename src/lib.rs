@@ -5,6 +5,17 @@ mod api_impl;
 mod r#trait;
 pub mod error;
 pub mod api;
+pub mod chat;
+pub mod download;
+pub mod estimate;
+pub mod export;
+pub mod library;
+pub mod manifest;
+pub mod queue;
+pub mod template;
+pub mod throttle;
+pub mod transcode;
+pub mod verify;
 
 pub use api_type::*;
 pub use r#type::*;
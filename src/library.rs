@@ -0,0 +1,216 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    api_type::Api,
+    download::{ComicDownloader, DownloadReport, Layout},
+    error::Error,
+    manifest::{write_atomic, Manifest},
+    responses::Comic,
+    throttle::RateLimiter,
+    ApiResult, Sort,
+};
+
+pub const LIBRARY_FILE: &'static str = "library.json";
+
+// Favourites seen by the last sync, to tell which ones were removed since.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Library {
+    pub synced_at: Option<String>,
+    pub favourites: Vec<LibraryEntry>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    pub cid: String,
+    pub title: String,
+}
+
+impl Library {
+    pub fn path(root: &Path) -> PathBuf {
+        root.join(LIBRARY_FILE)
+    }
+
+    pub async fn load(root: &Path) -> Result<Self, Error> {
+        match fs::read(Self::path(root)).await {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub async fn save(&self, root: &Path) -> Result<(), Error> {
+        write_atomic(&Self::path(root), &serde_json::to_vec_pretty(self)?).await
+    }
+}
+
+#[derive(Debug)]
+pub struct ComicSync {
+    pub cid: String,
+    pub title: String,
+    pub new_eps: Vec<String>,
+    pub updated_eps: Vec<String>,
+    pub download: DownloadReport,
+}
+
+#[derive(Debug)]
+pub struct SyncFailure {
+    pub cid: String,
+    pub title: String,
+    pub error: Error,
+}
+
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub new_comics: Vec<ComicSync>,
+    pub updated_comics: Vec<ComicSync>,
+    pub removed: Vec<LibraryEntry>,
+    pub unchanged: u64,
+    pub failed: Vec<SyncFailure>,
+}
+
+impl SyncReport {
+    pub fn new_eps(&self) -> usize {
+        self.new_comics
+            .iter()
+            .chain(self.updated_comics.iter())
+            .map(|comic| comic.new_eps.len())
+            .sum()
+    }
+}
+
+pub struct LibrarySync<'a> {
+    api: &'a Api,
+    root: PathBuf,
+    layout: Layout,
+    concurrency: usize,
+    limiter: Option<RateLimiter>,
+    check_updates: bool,
+}
+
+impl<'a> LibrarySync<'a> {
+    pub fn new(api: &'a Api, root: impl Into<PathBuf>) -> Self {
+        Self {
+            api,
+            root: root.into(),
+            layout: Layout::default(),
+            concurrency: 4,
+            limiter: None,
+            check_updates: true,
+        }
+    }
+
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    // When off, a comic whose ep count is unchanged is skipped without fetching its metadata.
+    pub fn check_updates(mut self, check_updates: bool) -> Self {
+        self.check_updates = check_updates;
+        self
+    }
+
+    pub async fn run(&self) -> ApiResult<SyncReport> {
+        let favourites: Vec<Comic> = self.api.favorites_stream(Sort::DescByDate).try_collect().await?;
+        let previous = Library::load(&self.root).await?;
+        let mut report = SyncReport::default();
+
+        for comic in favourites.iter() {
+            match self.sync_comic(comic).await {
+                Ok(Some((sync, true))) => report.new_comics.push(sync),
+                Ok(Some((sync, false))) => report.updated_comics.push(sync),
+                Ok(None) => report.unchanged += 1,
+                Err(error) => report.failed.push(SyncFailure {
+                    cid: comic.id.clone(),
+                    title: comic.title.clone(),
+                    error,
+                }),
+            }
+        }
+
+        report.removed = previous
+            .favourites
+            .into_iter()
+            .filter(|entry| !favourites.iter().any(|comic| comic.id == entry.cid))
+            .collect();
+        Library {
+            synced_at: Some(chrono::Utc::now().to_rfc3339()),
+            favourites: favourites
+                .iter()
+                .map(|comic| LibraryEntry {
+                    cid: comic.id.clone(),
+                    title: comic.title.clone(),
+                })
+                .collect(),
+        }
+        .save(&self.root)
+        .await?;
+        Ok(report)
+    }
+
+    // `None` when the local copy is already current; the flag is whether the comic is new.
+    async fn sync_comic(&self, comic: &Comic) -> ApiResult<Option<(ComicSync, bool)>> {
+        let manifest_path = Manifest::path(&self.root, &comic.id);
+        let before = Manifest::load(&manifest_path).await?;
+        if let Some(manifest) = before.as_ref() {
+            let complete = manifest.eps.iter().all(|ep| ep.is_complete());
+            if manifest.eps_count == comic.eps_count && complete {
+                if !self.check_updates {
+                    return Ok(None);
+                }
+                let metadata = self.api.comic_metadata(&comic.id).await?;
+                if manifest.is_current(&metadata) {
+                    return Ok(None);
+                }
+            }
+        }
+
+        let mut downloader = ComicDownloader::new(self.api, &comic.id, &self.root)
+            .layout(self.layout.clone())
+            .concurrency(self.concurrency);
+        if let Some(limiter) = self.limiter.as_ref() {
+            downloader = downloader.limiter(limiter.clone());
+        }
+        let download = downloader.run().await?;
+
+        let previous: HashMap<String, Option<String>> = before
+            .iter()
+            .flat_map(|manifest| manifest.eps.iter())
+            .filter(|ep| ep.is_complete())
+            .map(|ep| (ep.id.clone(), ep.updated_at.clone()))
+            .collect();
+        let after = Manifest::load(&manifest_path).await?.unwrap_or_default();
+        let mut sync = ComicSync {
+            cid: comic.id.clone(),
+            title: comic.title.clone(),
+            new_eps: Vec::new(),
+            updated_eps: Vec::new(),
+            download,
+        };
+        for ep in after.eps.iter().filter(|ep| ep.is_complete()) {
+            match previous.get(&ep.id) {
+                None => sync.new_eps.push(ep.title.clone()),
+                Some(updated_at) if *updated_at != ep.updated_at => sync.updated_eps.push(ep.title.clone()),
+                Some(_) => {}
+            }
+        }
+        Ok(Some((sync, before.is_none())))
+    }
+}
@@ -0,0 +1,175 @@
+use std::path::{Path, PathBuf};
+
+use hex::ToHex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+use crate::{
+    error::Error,
+    responses::{Ep, _ComicMetadata},
+};
+
+pub const EXTENSION: &'static str = "manifest.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub cid: String,
+    pub title: String,
+    pub eps_count: u64,
+    pub updated_at: String,
+    pub eps: Vec<EpRecord>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EpRecord {
+    pub id: String,
+    pub order: u64,
+    pub title: String,
+    pub updated_at: Option<String>,
+    pub pages_total: u64,
+    pub pages: Vec<PageRecord>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PageRecord {
+    pub id: String,
+    pub index: usize,
+    pub original_name: String,
+    // relative to the download root
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+    #[serde(default)]
+    pub transcoded: bool,
+}
+
+impl Manifest {
+    pub fn new(cid: &str) -> Self {
+        Self {
+            cid: cid.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn path(root: &Path, cid: &str) -> PathBuf {
+        root.join(format!("{}.{}", cid, EXTENSION))
+    }
+
+    pub async fn load(path: &Path) -> Result<Option<Self>, Error> {
+        match fs::read(path).await {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub async fn save(&mut self, path: &Path) -> Result<(), Error> {
+        self.eps.sort_by_key(|ep| ep.order);
+        for ep in self.eps.iter_mut() {
+            ep.pages.sort_by_key(|page| page.index);
+        }
+        write_atomic(path, &serde_json::to_vec_pretty(self)?).await
+    }
+
+    pub fn update(&mut self, comic: &_ComicMetadata) {
+        self.title = comic.metadata.title.clone();
+        self.eps_count = comic.metadata.eps_count;
+        self.updated_at = comic.updated_at.clone();
+    }
+
+    // Whether every ep of `comic` is recorded and fully downloaded.
+    pub fn is_current(&self, comic: &_ComicMetadata) -> bool {
+        self.eps_count == comic.metadata.eps_count
+            && self.updated_at == comic.updated_at
+            && self.eps.len() as u64 == comic.metadata.eps_count
+            && self.eps.iter().all(EpRecord::is_complete)
+    }
+
+    pub fn ep(&self, id: &str) -> Option<&EpRecord> {
+        self.eps.iter().find(|ep| ep.id == id)
+    }
+
+    pub fn ep_mut(&mut self, ep: &Ep) -> &mut EpRecord {
+        let position = match self.eps.iter().position(|v| v.id == ep.id) {
+            Some(position) => position,
+            None => {
+                self.eps.push(EpRecord {
+                    id: ep.id.clone(),
+                    ..Default::default()
+                });
+                self.eps.len() - 1
+            }
+        };
+        let record = &mut self.eps[position];
+        record.order = ep.order.unwrap_or_default();
+        record.title = ep.title.clone();
+        record.updated_at = ep.updated_at.clone();
+        record
+    }
+
+    pub fn pages(&self) -> impl Iterator<Item = (&EpRecord, &PageRecord)> {
+        self.eps
+            .iter()
+            .flat_map(|ep| ep.pages.iter().map(move |page| (ep, page)))
+    }
+}
+
+impl EpRecord {
+    pub fn is_complete(&self) -> bool {
+        self.pages_total > 0 && self.pages.len() as u64 == self.pages_total
+    }
+
+    pub fn page(&self, id: &str) -> Option<&PageRecord> {
+        self.pages.iter().find(|page| page.id == id)
+    }
+
+    pub fn upsert(&mut self, page: PageRecord) {
+        match self.pages.iter_mut().find(|v| v.id == page.id) {
+            Some(record) => *record = page,
+            None => self.pages.push(page),
+        }
+    }
+
+    pub fn to_ep(&self) -> Ep {
+        Ep {
+            id: self.id.clone(),
+            order: Some(self.order),
+            title: self.title.clone(),
+            updated_at: self.updated_at.clone(),
+        }
+    }
+}
+
+pub fn sha256(data: &[u8]) -> String {
+    Sha256::digest(data).encode_hex()
+}
+
+// Sibling file a write goes to before it is renamed over `path`.
+pub(crate) fn part_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    PathBuf::from(partial)
+}
+
+// Writes through a `.part` file so readers never see a half written `path`.
+pub(crate) async fn write_atomic(path: &Path, data: &[u8]) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let partial = part_path(path);
+    fs::write(&partial, data).await?;
+    fs::rename(&partial, path).await?;
+    Ok(())
+}
+
+// `write_atomic` for blocking callers.
+pub(crate) fn write_atomic_blocking(path: &Path, data: &[u8]) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let partial = part_path(path);
+    std::fs::write(&partial, data)?;
+    std::fs::rename(&partial, path)?;
+    Ok(())
+}
@@ -0,0 +1,532 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use futures::{
+    future::{AbortHandle, Abortable},
+    stream::FuturesUnordered,
+    StreamExt,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    sync::{broadcast, Notify},
+};
+
+use crate::{
+    api_type::Api,
+    download::{ComicDownloader, EpSelection},
+    error::Error,
+    export::{cbz, epub, pdf, Split},
+    manifest::{write_atomic, Manifest},
+};
+
+const EVENT_CAPACITY: usize = 256;
+// room left between neighbouring positions for later moves
+const POSITION_GAP: u64 = 1 << 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputFormat {
+    // only the downloaded images
+    #[default]
+    Images,
+    Cbz(Split),
+    Epub,
+    Pdf(Split),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobState {
+    Queued,
+    Running,
+    Paused,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Done | Self::Failed | Self::Cancelled)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u64,
+    pub cid: String,
+    pub episodes: EpSelection,
+    pub format: OutputFormat,
+    // higher runs first, ties run by `position`
+    pub priority: i32,
+    #[serde(default)]
+    pub position: u64,
+    pub state: JobState,
+    pub error: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum QueueEvent {
+    Added(Job),
+    StateChanged {
+        id: u64,
+        state: JobState,
+        error: Option<String>,
+    },
+    PriorityChanged {
+        id: u64,
+        priority: i32,
+    },
+    Moved {
+        id: u64,
+        before: u64,
+    },
+    Removed(u64),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct QueueFile {
+    next_id: u64,
+    jobs: Vec<Job>,
+}
+
+struct QueueState {
+    file: QueueFile,
+    running: HashMap<u64, AbortHandle>,
+    out_dir: PathBuf,
+    concurrency: usize,
+}
+
+struct Inner {
+    path: PathBuf,
+    root: PathBuf,
+    state: Mutex<QueueState>,
+    wake: Notify,
+    stopping: AtomicBool,
+    saving: tokio::sync::Mutex<()>,
+    events: broadcast::Sender<QueueEvent>,
+}
+
+// Cloning shares the same queue, so one handle can `run` while others edit it.
+#[derive(Clone)]
+pub struct DownloadQueue {
+    inner: Arc<Inner>,
+}
+
+impl DownloadQueue {
+    // Jobs left running by a previous process are queued again; downloads resume from their manifests.
+    pub async fn open(path: impl Into<PathBuf>, root: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let root = root.into();
+        let mut file: QueueFile = match fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => QueueFile::default(),
+            Err(err) => Err(err)?,
+        };
+        for job in file.jobs.iter_mut().filter(|job| job.state == JobState::Running) {
+            job.state = JobState::Queued;
+        }
+        // files written before positions existed
+        if file.jobs.iter().any(|job| job.position == 0) {
+            renumber(&mut file.jobs);
+        }
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        Ok(Self {
+            inner: Arc::new(Inner {
+                path,
+                state: Mutex::new(QueueState {
+                    file,
+                    running: HashMap::new(),
+                    out_dir: root.clone(),
+                    concurrency: 2,
+                }),
+                root,
+                wake: Notify::new(),
+                stopping: AtomicBool::new(false),
+                saving: tokio::sync::Mutex::new(()),
+                events,
+            }),
+        })
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<QueueEvent> {
+        self.inner.events.subscribe()
+    }
+
+    pub fn set_concurrency(&self, concurrency: usize) {
+        self.inner.state.lock().unwrap().concurrency = concurrency.max(1);
+        self.inner.wake.notify_one();
+    }
+
+    pub fn set_output_dir(&self, out_dir: impl Into<PathBuf>) {
+        self.inner.state.lock().unwrap().out_dir = out_dir.into();
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.inner.root
+    }
+
+    // In the order they will run.
+    pub fn jobs(&self) -> Vec<Job> {
+        let mut jobs = self.inner.state.lock().unwrap().file.jobs.clone();
+        jobs.sort_by_key(run_order);
+        jobs
+    }
+
+    pub fn job(&self, id: u64) -> Option<Job> {
+        self.inner.state.lock().unwrap().file.jobs.iter().find(|job| job.id == id).cloned()
+    }
+
+    pub async fn push(
+        &self,
+        cid: &str,
+        episodes: EpSelection,
+        format: OutputFormat,
+        priority: i32,
+    ) -> Result<u64, Error> {
+        let job = {
+            let mut state = self.inner.state.lock().unwrap();
+            state.file.next_id += 1;
+            let position = state.file.jobs.iter().map(|job| job.position).max().unwrap_or(0) + POSITION_GAP;
+            let job = Job {
+                id: state.file.next_id,
+                cid: cid.to_string(),
+                episodes,
+                format,
+                priority,
+                position,
+                state: JobState::Queued,
+                error: None,
+                created_at: chrono::Utc::now().to_rfc3339(),
+            };
+            state.file.jobs.push(job.clone());
+            job
+        };
+        self.save().await?;
+        let id = job.id;
+        self.emit(QueueEvent::Added(job));
+        self.inner.wake.notify_one();
+        Ok(id)
+    }
+
+    // Aborts the job if it is running; its progress stays in the manifest.
+    pub async fn pause(&self, id: u64) -> Result<(), Error> {
+        self.transition(id, JobState::Paused, |state| matches!(state, JobState::Queued | JobState::Running))
+            .await
+    }
+
+    // Also retries failed and cancelled jobs.
+    pub async fn resume(&self, id: u64) -> Result<(), Error> {
+        self.transition(id, JobState::Queued, |state| {
+            matches!(state, JobState::Paused | JobState::Failed | JobState::Cancelled)
+        })
+        .await
+    }
+
+    pub async fn cancel(&self, id: u64) -> Result<(), Error> {
+        self.transition(id, JobState::Cancelled, |state| !state.is_finished()).await
+    }
+
+    pub async fn set_priority(&self, id: u64, priority: i32) -> Result<(), Error> {
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            let job = find(&mut state.file, id)?;
+            job.priority = priority;
+        }
+        self.save().await?;
+        self.emit(QueueEvent::PriorityChanged { id, priority });
+        Ok(())
+    }
+
+    // Moves the job right before `before` in the run order. Both need the same priority,
+    // use `set_priority` to move a job between priorities.
+    pub async fn move_before(&self, id: u64, before: u64) -> Result<(), Error> {
+        if id == before {
+            return Ok(());
+        }
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            let jobs = &mut state.file.jobs;
+            let priority = find_in(jobs, id)?.priority;
+            if find_in(jobs, before)?.priority != priority {
+                Err(Error::Queue(format!("jobs {} and {} have different priorities", id, before)))?;
+            }
+            if let Some(position) = position_before(jobs, id, before) {
+                find_in(jobs, id)?.position = position;
+            } else {
+                renumber(jobs);
+                let position = position_before(jobs, id, before).unwrap_or_default();
+                find_in(jobs, id)?.position = position;
+            }
+        }
+        self.save().await?;
+        self.emit(QueueEvent::Moved { id, before });
+        Ok(())
+    }
+
+    pub async fn remove(&self, id: u64) -> Result<(), Error> {
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            find(&mut state.file, id)?;
+            if let Some(handle) = state.running.remove(&id) {
+                handle.abort();
+            }
+            state.file.jobs.retain(|job| job.id != id);
+        }
+        self.save().await?;
+        self.emit(QueueEvent::Removed(id));
+        Ok(())
+    }
+
+    pub async fn clear_finished(&self) -> Result<(), Error> {
+        let removed: Vec<u64> = {
+            let mut state = self.inner.state.lock().unwrap();
+            let removed = state
+                .file
+                .jobs
+                .iter()
+                .filter(|job| job.state.is_finished())
+                .map(|job| job.id)
+                .collect();
+            state.file.jobs.retain(|job| !job.state.is_finished());
+            removed
+        };
+        self.save().await?;
+        for id in removed {
+            self.emit(QueueEvent::Removed(id));
+        }
+        Ok(())
+    }
+
+    // Runs queued jobs until none are left queued or running.
+    // Runs jobs until nothing is queued or running, then returns. Jobs added afterwards wait for
+    // the next call; use `serve` to keep picking them up.
+    pub async fn run(&self, api: &Api) -> Result<(), Error> {
+        self.drive(api, false).await
+    }
+
+    // Like `run`, but keeps waiting for new or resumed jobs when idle, until `shutdown` is called.
+    pub async fn serve(&self, api: &Api) -> Result<(), Error> {
+        self.drive(api, true).await
+    }
+
+    // Stops `run`/`serve` for good. Running jobs are aborted and queued again so that the next
+    // `open` of the queue file resumes them.
+    pub fn shutdown(&self) {
+        self.inner.stopping.store(true, Ordering::SeqCst);
+        self.inner.wake.notify_one();
+    }
+
+    async fn drive(&self, api: &Api, keep_alive: bool) -> Result<(), Error> {
+        let mut tasks = FuturesUnordered::new();
+        loop {
+            if self.inner.stopping.load(Ordering::SeqCst) {
+                self.requeue_running();
+                return self.save().await;
+            }
+            for job in self.take_runnable() {
+                let (handle, registration) = AbortHandle::new_pair();
+                self.inner.state.lock().unwrap().running.insert(job.id, handle);
+                self.emit(QueueEvent::StateChanged {
+                    id: job.id,
+                    state: JobState::Running,
+                    error: None,
+                });
+                tasks.push(async move {
+                    let result = Abortable::new(self.execute(api, &job), registration).await;
+                    (job.id, result)
+                });
+            }
+            self.save().await?;
+            if tasks.is_empty() {
+                if !keep_alive {
+                    return Ok(());
+                }
+                self.inner.wake.notified().await;
+                continue;
+            }
+            tokio::select! {
+                Some((id, result)) = tasks.next() => {
+                    // an aborted job was already moved to its new state
+                    if let Ok(result) = result {
+                        self.inner.state.lock().unwrap().running.remove(&id);
+                        let (state, error) = match result {
+                            Ok(()) => (JobState::Done, None),
+                            Err(err) => (JobState::Failed, Some(format!("{:?}", err))),
+                        };
+                        self.finish(id, state, error);
+                    }
+                }
+                _ = self.inner.wake.notified() => {}
+            }
+        }
+    }
+
+    async fn execute(&self, api: &Api, job: &Job) -> Result<(), Error> {
+        let out_dir = self.inner.state.lock().unwrap().out_dir.clone();
+        let report = ComicDownloader::new(api, &job.cid, self.root())
+            .episodes(job.episodes.clone())
+            .run()
+            .await?;
+        if let Some(page) = report.failed.first() {
+            Err(Error::Download(format!(
+                "{} pages failed, first {}: {:?}",
+                report.failed.len(),
+                page.path.to_string_lossy(),
+                page.error
+            )))?;
+        }
+        if job.format == OutputFormat::Images {
+            return Ok(());
+        }
+        let manifest = Manifest::load(&Manifest::path(self.root(), &job.cid))
+            .await?
+            .ok_or_else(|| Error::NotFound(job.cid.clone()))?;
+        let comic = api.comic_metadata(&job.cid).await?;
+        match job.format {
+            OutputFormat::Images => {}
+            OutputFormat::Cbz(split) => {
+                cbz::write_cbz(self.root(), &manifest, &job.episodes, &comic, split, &out_dir)?;
+            }
+            OutputFormat::Epub => {
+                let cover = epub::cover(api, &comic).await.ok();
+                epub::write_epub(self.root(), &manifest, &job.episodes, &comic, cover.as_deref(), &out_dir)?;
+            }
+            OutputFormat::Pdf(split) => {
+                pdf::write_pdf(self.root(), &manifest, &job.episodes, &comic, split, &out_dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn requeue_running(&self) {
+        let requeued: Vec<u64> = {
+            let mut state = self.inner.state.lock().unwrap();
+            let running: Vec<(u64, AbortHandle)> = state.running.drain().collect();
+            for (id, handle) in running.iter() {
+                handle.abort();
+                if let Ok(job) = find(&mut state.file, *id) {
+                    job.state = JobState::Queued;
+                }
+            }
+            running.into_iter().map(|(id, _)| id).collect()
+        };
+        for id in requeued {
+            self.emit(QueueEvent::StateChanged {
+                id,
+                state: JobState::Queued,
+                error: None,
+            });
+        }
+    }
+
+    fn take_runnable(&self) -> Vec<Job> {
+        let mut state = self.inner.state.lock().unwrap();
+        let slots = state.concurrency.saturating_sub(state.running.len());
+        let mut queued: Vec<&mut Job> = state
+            .file
+            .jobs
+            .iter_mut()
+            .filter(|job| job.state == JobState::Queued)
+            .collect();
+        queued.sort_by_key(|job| run_order(job));
+        queued
+            .into_iter()
+            .take(slots)
+            .map(|job| {
+                job.state = JobState::Running;
+                job.error = None;
+                job.clone()
+            })
+            .collect()
+    }
+
+    fn finish(&self, id: u64, new: JobState, error: Option<String>) {
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            match find(&mut state.file, id) {
+                Ok(job) if job.state == JobState::Running => {
+                    job.state = new;
+                    job.error = error.clone();
+                }
+                _ => return,
+            }
+        }
+        self.emit(QueueEvent::StateChanged { id, state: new, error });
+    }
+
+    async fn transition(&self, id: u64, new: JobState, allowed: impl Fn(JobState) -> bool) -> Result<(), Error> {
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            let job = find(&mut state.file, id)?;
+            if !allowed(job.state) {
+                return Ok(());
+            }
+            job.state = new;
+            job.error = None;
+            if let Some(handle) = state.running.remove(&id) {
+                handle.abort();
+            }
+        }
+        self.save().await?;
+        self.emit(QueueEvent::StateChanged {
+            id,
+            state: new,
+            error: None,
+        });
+        self.inner.wake.notify_one();
+        Ok(())
+    }
+
+    async fn save(&self) -> Result<(), Error> {
+        let _saving = self.inner.saving.lock().await;
+        let data = serde_json::to_vec_pretty(&self.inner.state.lock().unwrap().file)?;
+        write_atomic(&self.inner.path, &data).await
+    }
+
+    fn emit(&self, event: QueueEvent) {
+        // no subscribers is not an error
+        let _ = self.inner.events.send(event);
+    }
+}
+
+fn find(file: &mut QueueFile, id: u64) -> Result<&mut Job, Error> {
+    find_in(&mut file.jobs, id)
+}
+
+fn find_in(jobs: &mut [Job], id: u64) -> Result<&mut Job, Error> {
+    jobs.iter_mut()
+        .find(|job| job.id == id)
+        .ok_or_else(|| Error::NotFound(format!("job {}", id)))
+}
+
+fn run_order(job: &Job) -> (i64, u64, u64) {
+    (-(job.priority as i64), job.position, job.id)
+}
+
+// A free position between `before` and the job running just ahead of it, if there is room.
+fn position_before(jobs: &[Job], id: u64, before: u64) -> Option<u64> {
+    let target = jobs.iter().find(|job| job.id == before)?;
+    let ahead = jobs
+        .iter()
+        .filter(|job| job.id != id && job.priority == target.priority && run_order(job) < run_order(target))
+        .map(|job| job.position)
+        .max()
+        .unwrap_or(0);
+    (target.position - ahead >= 2).then(|| ahead + (target.position - ahead) / 2)
+}
+
+// Spreads positions out again, keeping the run order.
+fn renumber(jobs: &mut [Job]) {
+    let mut order: Vec<usize> = (0..jobs.len()).collect();
+    order.sort_by_key(|index| run_order(&jobs[*index]));
+    for (rank, index) in order.into_iter().enumerate() {
+        jobs[index].position = (rank as u64 + 1) * POSITION_GAP;
+    }
+}
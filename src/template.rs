@@ -0,0 +1,308 @@
+use std::{path::PathBuf, str::FromStr};
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{download::PageContext, error::Error};
+
+// Most filesystems cap a single path component at 255 bytes.
+pub const MAX_COMPONENT_BYTES: usize = 255;
+
+const RESERVED: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Title,
+    Author,
+    Cid,
+    EpOrder,
+    EpTitle,
+    PageIndex,
+    OriginalName,
+    Ext,
+}
+
+impl FromStr for Field {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "title" => Self::Title,
+            "author" => Self::Author,
+            "cid" => Self::Cid,
+            "ep_order" => Self::EpOrder,
+            "ep_title" => Self::EpTitle,
+            "page_index" => Self::PageIndex,
+            "original_name" => Self::OriginalName,
+            "ext" => Self::Ext,
+            _ => Err(Error::Template(format!("unknown placeholder {{{}}}", s)))?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Literal(String),
+    Field { field: Field, width: usize },
+}
+
+#[derive(Debug, Clone)]
+pub struct PathTemplate {
+    source: String,
+    components: Vec<Vec<Token>>,
+    max_component_bytes: usize,
+}
+
+impl PathTemplate {
+    // Mirrors `Layout::Nested`.
+    pub const NESTED: &'static str = "{cid}/{ep_order:4}/{page_index:4}.{ext}";
+    pub const TITLED: &'static str = "{title}/{ep_order:4} {ep_title}/{page_index:4}.{ext}";
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn max_component_bytes(mut self, max: usize) -> Self {
+        self.max_component_bytes = max.max(16);
+        self
+    }
+
+    pub fn render(&self, context: &PageContext) -> PathBuf {
+        let last = self.components.len().saturating_sub(1);
+        self.components
+            .iter()
+            .enumerate()
+            .map(|(index, tokens)| {
+                let mut component = String::new();
+                for token in tokens {
+                    match token {
+                        Token::Literal(text) => component.push_str(text),
+                        Token::Field { field, width } => {
+                            component.push_str(&value(context, *field, *width))
+                        }
+                    }
+                }
+                sanitize(&component, self.max_component_bytes, index == last)
+            })
+            .collect()
+    }
+}
+
+impl FromStr for PathTemplate {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut components = Vec::new();
+        for part in s.split(['/', '\\']).filter(|v| !v.is_empty()) {
+            components.push(parse_component(part)?);
+        }
+        if components.is_empty() {
+            Err(Error::Template("empty template".to_owned()))?;
+        }
+        Ok(Self {
+            source: s.to_string(),
+            components,
+            max_component_bytes: MAX_COMPONENT_BYTES,
+        })
+    }
+}
+
+fn parse_component(part: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut literal = String::new();
+    let mut chars = part.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => Err(Error::Template(format!("unclosed placeholder in {}", part)))?,
+                    }
+                }
+                let (name, width) = match name.split_once(':') {
+                    Some((name, width)) => (
+                        name.to_string(),
+                        width
+                            .parse()
+                            .map_err(|_| Error::Template(format!("invalid width in {{{}}}", name)))?,
+                    ),
+                    None => (name, 0),
+                };
+                if !literal.is_empty() {
+                    tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                }
+                tokens.push(Token::Field {
+                    field: name.parse()?,
+                    width,
+                });
+            }
+            '}' => Err(Error::Template(format!("unmatched }} in {}", part)))?,
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+    Ok(tokens)
+}
+
+fn value(context: &PageContext, field: Field, width: usize) -> String {
+    match field {
+        Field::Title => context.comic.metadata.title.clone(),
+        Field::Author => context.comic.metadata.author.clone(),
+        Field::Cid => context.comic.metadata.id.clone(),
+        Field::EpOrder => format!("{:0width$}", context.ep_order(), width = width),
+        Field::EpTitle => context.ep.title.clone(),
+        Field::PageIndex => format!("{:0width$}", context.index, width = width),
+        Field::OriginalName => context.page.media.filename().to_string(),
+        Field::Ext => context.extension().to_string(),
+    }
+}
+
+// Makes a single path component safe to create on common filesystems.
+pub fn sanitize(component: &str, max_bytes: usize, keep_extension: bool) -> String {
+    let mut cleaned: String = component
+        .nfc()
+        .filter(|c| !c.is_control())
+        .map(|c| if RESERVED.contains(&c) { '_' } else { c })
+        .collect();
+    cleaned = cleaned.trim().trim_end_matches(['.', ' ']).to_string();
+    if cleaned.is_empty() || cleaned == "." || cleaned == ".." {
+        cleaned = "_".to_owned();
+    }
+    let stem = cleaned.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES.iter().any(|v| v.eq_ignore_ascii_case(stem)) {
+        cleaned.insert(0, '_');
+    }
+    if cleaned.len() <= max_bytes {
+        return cleaned;
+    }
+    let extension = match cleaned.rsplit_once('.') {
+        Some((_, extension)) if keep_extension && extension.len() < max_bytes / 2 => {
+            format!(".{}", extension)
+        }
+        _ => String::new(),
+    };
+    let mut truncated = truncate(&cleaned[..cleaned.len() - extension.len()], max_bytes - extension.len())
+        .trim_end_matches(['.', ' '])
+        .to_string();
+    truncated.push_str(&extension);
+    truncated
+}
+
+pub fn truncate(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(template: &str) -> String {
+        match template.parse::<PathTemplate>() {
+            Err(Error::Template(message)) => message,
+            other => panic!("expected a template error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reserved_characters_become_underscores() {
+        assert_eq!(sanitize("a/b?c:d", MAX_COMPONENT_BYTES, false), "a_b_c_d");
+        assert_eq!(sanitize("<x|y>*\"z\\", MAX_COMPONENT_BYTES, false), "_x_y___z_");
+        assert_eq!(sanitize("tab\there", MAX_COMPONENT_BYTES, false), "tabhere");
+    }
+
+    #[test]
+    fn trailing_dots_and_spaces_are_trimmed() {
+        assert_eq!(sanitize("  name. . ", MAX_COMPONENT_BYTES, false), "name");
+    }
+
+    #[test]
+    fn empty_and_dot_components() {
+        assert_eq!(sanitize("", MAX_COMPONENT_BYTES, false), "_");
+        assert_eq!(sanitize(".", MAX_COMPONENT_BYTES, false), "_");
+        assert_eq!(sanitize("..", MAX_COMPONENT_BYTES, false), "_");
+        assert_eq!(sanitize("   ", MAX_COMPONENT_BYTES, false), "_");
+    }
+
+    #[test]
+    fn windows_reserved_names() {
+        assert_eq!(sanitize("CON.jpg", MAX_COMPONENT_BYTES, true), "_CON.jpg");
+        assert_eq!(sanitize("con", MAX_COMPONENT_BYTES, false), "_con");
+        assert_eq!(sanitize("lpt9.tar.gz", MAX_COMPONENT_BYTES, true), "_lpt9.tar.gz");
+        assert_eq!(sanitize("CONSOLE", MAX_COMPONENT_BYTES, false), "CONSOLE");
+    }
+
+    #[test]
+    fn truncates_multibyte_at_char_boundary() {
+        // 3 bytes each
+        let cjk = sanitize(&"漫画".repeat(100), 100, false);
+        assert_eq!(cjk.len(), 99);
+        assert!(cjk.chars().all(|c| c == '漫' || c == '画'));
+        // 4 bytes each
+        let emoji = sanitize(&"😀".repeat(100), 255, false);
+        assert_eq!(emoji.len(), 252);
+        assert_eq!(truncate("a😀", 3), "a");
+        assert_eq!(truncate("abc", 10), "abc");
+    }
+
+    #[test]
+    fn truncation_keeps_extension() {
+        let name = sanitize(&format!("{}.jpg", "長".repeat(200)), 255, true);
+        assert!(name.ends_with(".jpg"));
+        assert!(name.len() <= 255);
+        let directory = sanitize(&format!("{}.jpg", "a".repeat(300)), 255, false);
+        assert_eq!(directory.len(), 255);
+        assert!(!directory.ends_with(".jpg"));
+    }
+
+    #[test]
+    fn normalizes_to_nfc() {
+        assert_eq!(sanitize("e\u{301}", MAX_COMPONENT_BYTES, false), "\u{e9}");
+    }
+
+    #[test]
+    fn parses_fields_and_escapes() {
+        let template: PathTemplate = "{{{cid}}}/{ep_order:4}_{page_index}.{ext}".parse().unwrap();
+        assert_eq!(template.components.len(), 2);
+        assert!(matches!(
+            template.components[0].as_slice(),
+            [Token::Literal(open), Token::Field { field: Field::Cid, width: 0 }, Token::Literal(close)]
+                if open == "{" && close == "}"
+        ));
+        assert!(matches!(
+            template.components[1][0],
+            Token::Field { field: Field::EpOrder, width: 4 }
+        ));
+        assert!(PathTemplate::NESTED.parse::<PathTemplate>().is_ok());
+        assert!(PathTemplate::TITLED.parse::<PathTemplate>().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert!(parse_error("{title").contains("unclosed"));
+        assert!(parse_error("{title}/{nope}.{ext}").contains("unknown placeholder {nope}"));
+        assert!(parse_error("a}b").contains("unmatched"));
+        assert!(parse_error("{ep_order:x}").contains("invalid width"));
+        assert!(parse_error("//").contains("empty"));
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// Token bucket shared by every clone; a rate of `None` means unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug, Default)]
+struct Bucket {
+    // bytes per second
+    rate: Option<u64>,
+    // may go negative when a chunk is larger than the burst
+    tokens: f64,
+    updated: Option<Instant>,
+}
+
+impl Bucket {
+    fn refill(&mut self, rate: u64, now: Instant) {
+        if let Some(updated) = self.updated {
+            let elapsed = now.duration_since(updated).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * rate as f64).min(rate as f64);
+        } else {
+            self.tokens = rate as f64;
+        }
+        self.updated = Some(now);
+    }
+}
+
+impl RateLimiter {
+    pub fn new(rate: Option<u64>) -> Self {
+        let limiter = Self::default();
+        limiter.set_rate(rate);
+        limiter
+    }
+
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    pub fn rate(&self) -> Option<u64> {
+        self.bucket.lock().unwrap().rate
+    }
+
+    // Takes effect for the next chunk of every download sharing this limiter.
+    pub fn set_rate(&self, rate: Option<u64>) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.rate = rate.filter(|v| *v > 0);
+        match bucket.rate {
+            Some(rate) => bucket.tokens = bucket.tokens.min(rate as f64),
+            None => {
+                bucket.tokens = 0.0;
+                bucket.updated = None;
+            }
+        }
+    }
+
+    pub async fn acquire(&self, bytes: u64) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let Some(rate) = bucket.rate else {
+                return;
+            };
+            bucket.refill(rate, Instant::now());
+            bucket.tokens -= bytes as f64;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.tokens / rate as f64)
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
@@ -0,0 +1,262 @@
+use std::path::{Path, PathBuf};
+
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ExtendedColorType, ImageEncoder};
+use rayon::prelude::*;
+
+use crate::{
+    error::Error,
+    manifest::{sha256, write_atomic_blocking, Manifest},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetFormat {
+    Jpeg,
+    // needs the `webp` feature, the bundled encoder of `image` is lossless only
+    WebP,
+    // needs the `avif` feature; such pages can go into CBZ and EPUB but not PDF, since `image`
+    // cannot decode AVIF
+    Avif,
+}
+
+impl TargetFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::WebP => "webp",
+            Self::Avif => "avif",
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        match self {
+            Self::Jpeg => true,
+            Self::WebP => cfg!(feature = "webp"),
+            Self::Avif => cfg!(feature = "avif"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TranscodeReport {
+    pub pages: u64,
+    // kept as is because the re-encoded file was not smaller, these still carry their metadata;
+    // always 0 unless `keep_smaller_originals` is on
+    pub pages_kept: u64,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    pub failed: Vec<(PathBuf, Error)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Transcoder {
+    format: TargetFormat,
+    quality: u8,
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+    #[cfg_attr(not(feature = "avif"), allow(dead_code))]
+    avif_speed: u8,
+    threads: usize,
+    keep_smaller_originals: bool,
+}
+
+struct Transcoded {
+    path: PathBuf,
+    size: u64,
+    sha256: String,
+    original_size: u64,
+    kept: bool,
+}
+
+impl Transcoder {
+    pub fn new(format: TargetFormat) -> Result<Self, Error> {
+        if !format.is_available() {
+            Err(Error::Transcode(format!(
+                "{} encoding requires the `{}` feature",
+                format.extension(),
+                format.extension()
+            )))?;
+        }
+        Ok(Self {
+            format,
+            quality: 80,
+            max_width: None,
+            max_height: None,
+            avif_speed: 6,
+            threads: 0,
+            keep_smaller_originals: false,
+        })
+    }
+
+    pub fn format(&self) -> TargetFormat {
+        self.format
+    }
+
+    // 1 to 100
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.quality = quality.clamp(1, 100);
+        self
+    }
+
+    // Pages are scaled down to fit, keeping the aspect ratio; they are never scaled up.
+    pub fn max_size(mut self, width: Option<u32>, height: Option<u32>) -> Self {
+        self.max_width = width;
+        self.max_height = height;
+        self
+    }
+
+    // 1 (slowest, smallest) to 10
+    pub fn avif_speed(mut self, speed: u8) -> Self {
+        self.avif_speed = speed.clamp(1, 10);
+        self
+    }
+
+    // 0 uses one thread per CPU.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    // Keeps a page untouched, metadata included, when re-encoding it would not make it smaller
+    // and it needs no downscaling. Off by default so every page is stripped.
+    pub fn keep_smaller_originals(mut self, keep: bool) -> Self {
+        self.keep_smaller_originals = keep;
+        self
+    }
+
+    // Only pixels are re-encoded, so EXIF, ICC and other metadata are dropped.
+    pub fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let image = self.downscale(image::load_from_memory(data)?);
+        let image = image.to_rgb8();
+        let (width, height) = image.dimensions();
+        let mut out = Vec::new();
+        match self.format {
+            TargetFormat::Jpeg => {
+                JpegEncoder::new_with_quality(&mut out, self.quality).write_image(
+                    image.as_raw(),
+                    width,
+                    height,
+                    ExtendedColorType::Rgb8,
+                )?;
+            }
+            #[cfg(feature = "webp")]
+            TargetFormat::WebP => {
+                out = webp::Encoder::from_rgb(image.as_raw(), width, height)
+                    .encode(self.quality as f32)
+                    .to_vec();
+            }
+            #[cfg(feature = "avif")]
+            TargetFormat::Avif => {
+                image::codecs::avif::AvifEncoder::new_with_speed_quality(&mut out, self.avif_speed, self.quality)
+                    .write_image(image.as_raw(), width, height, ExtendedColorType::Rgb8)?;
+            }
+            #[allow(unreachable_patterns)]
+            format => Err(Error::Transcode(format!("{} encoding is not enabled", format.extension())))?,
+        }
+        Ok(out)
+    }
+
+    fn downscale(&self, image: DynamicImage) -> DynamicImage {
+        let max_width = self.max_width.unwrap_or(u32::MAX);
+        let max_height = self.max_height.unwrap_or(u32::MAX);
+        if image.width() <= max_width && image.height() <= max_height {
+            return image;
+        }
+        image.resize(max_width, max_height, FilterType::Lanczos3)
+    }
+
+    fn resizes(&self, data: &[u8]) -> bool {
+        match image::ImageReader::new(std::io::Cursor::new(data))
+            .with_guessed_format()
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok())
+        {
+            Some((width, height)) => {
+                width > self.max_width.unwrap_or(u32::MAX) || height > self.max_height.unwrap_or(u32::MAX)
+            }
+            None => false,
+        }
+    }
+
+    fn transcode_file(&self, root: &Path, path: &Path) -> Result<Transcoded, Error> {
+        let source = root.join(path);
+        let data = std::fs::read(&source)?;
+        let encoded = self.encode(&data)?;
+        // re-encoding an already compressed page can grow it; keep the original then
+        if self.keep_smaller_originals && encoded.len() >= data.len() && !self.resizes(&data) {
+            return Ok(Transcoded {
+                path: path.to_path_buf(),
+                size: data.len() as u64,
+                sha256: sha256(&data),
+                original_size: data.len() as u64,
+                kept: true,
+            });
+        }
+        let target = path.with_extension(self.format.extension());
+        let destination = root.join(&target);
+        write_atomic_blocking(&destination, &encoded)?;
+        if destination != source {
+            std::fs::remove_file(&source)?;
+        }
+        Ok(Transcoded {
+            path: target,
+            size: encoded.len() as u64,
+            sha256: sha256(&encoded),
+            original_size: data.len() as u64,
+            kept: false,
+        })
+    }
+
+    // Re-encodes every page not transcoded yet and points the manifest at the new files.
+    pub async fn transcode_manifest(&self, root: &Path, manifest: &mut Manifest) -> Result<TranscodeReport, Error> {
+        let jobs: Vec<(usize, usize, PathBuf)> = manifest
+            .eps
+            .iter()
+            .enumerate()
+            .flat_map(|(ep, record)| {
+                record
+                    .pages
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, page)| !page.transcoded)
+                    .map(move |(page, record)| (ep, page, record.path.clone()))
+            })
+            .collect();
+        let transcoder = self.clone();
+        let root = root.to_path_buf();
+        let results = tokio::task::spawn_blocking(move || {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(transcoder.threads)
+                .build()
+                .map_err(|err| Error::Transcode(err.to_string()))?;
+            Ok::<_, Error>(pool.install(|| {
+                jobs.into_par_iter()
+                    .map(|(ep, page, path)| {
+                        let result = transcoder.transcode_file(&root, &path);
+                        (ep, page, path, result)
+                    })
+                    .collect::<Vec<_>>()
+            }))
+        })
+        .await
+        .map_err(|err| Error::Transcode(err.to_string()))??;
+
+        let mut report = TranscodeReport::default();
+        for (ep, page, path, result) in results {
+            match result {
+                Ok(transcoded) => {
+                    let record = &mut manifest.eps[ep].pages[page];
+                    record.path = transcoded.path;
+                    record.size = transcoded.size;
+                    record.sha256 = transcoded.sha256;
+                    record.transcoded = true;
+                    report.pages += 1;
+                    report.pages_kept += transcoded.kept as u64;
+                    report.bytes_before += transcoded.original_size;
+                    report.bytes_after += transcoded.size;
+                }
+                Err(err) => report.failed.push((path, err)),
+            }
+        }
+        Ok(report)
+    }
+}
@@ -89,6 +89,68 @@ pub enum Sort {
     MaxSearch,
 }
 
+#[derive(Debug, Clone)]
+pub enum ComicFilter {
+    Category(String),
+    Tag(String),
+    Author(String),
+    Creator(String),
+    ChineseTeam(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    pub(super) keyword: String,
+    pub(super) categories: Vec<String>,
+    pub(super) sort: Sort,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub enum RankingPeriod {
+    #[default]
+    H24,
+    D7,
+    D30,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub enum RankingKind {
+    #[default]
+    Views,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GamePlatform {
+    #[default]
+    Android,
+    Ios,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MirrorKind {
+    Node,
+    BitTorrent,
+    OneDrive,
+    S3Sg,
+    S3Sg2,
+    S3Us,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mirror {
+    pub kind: MirrorKind,
+    pub url: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MirrorProbe {
+    pub mirror: Mirror,
+    pub status: Option<u16>,
+    pub latency: Option<std::time::Duration>,
+    pub size: Option<u64>,
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Response<T: Debug> {
     #[serde(deserialize_with="num_correct_deserializer")]
@@ -115,6 +177,36 @@ pub mod responses {
         pub(crate) file_server: String,
     }
 
+    #[derive(Debug, Deserialize)]
+    pub struct Init {
+        #[serde(default)]
+        pub addresses: Vec<String>,
+        #[serde(rename = "imageServer")]
+        pub image_server: Option<String>,
+        #[serde(rename = "imageServers", default)]
+        pub image_servers: Vec<String>,
+        #[serde(rename = "latestApplication")]
+        pub latest_application: Option<LatestApplication>,
+        #[serde(rename = "apiLevel")]
+        pub api_level: Option<u64>,
+        #[serde(rename = "minApiLevel")]
+        pub min_api_level: Option<u64>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct LatestApplication {
+        #[serde(rename = "_id")]
+        pub id: Option<String>,
+        #[serde(rename = "downloadUrl")]
+        pub download_url: Option<String>,
+        pub version: String,
+        #[serde(rename = "updateContent")]
+        pub update_content: Option<String>,
+        pub apk: Option<PictureDownloadResounce>,
+        pub created_at: Option<String>,
+        pub updated_at: Option<String>,
+    }
+
     #[derive(Debug, Deserialize)]
     pub struct Auth {
         pub token: String,
@@ -167,6 +259,45 @@ pub mod responses {
         pub verified: bool,
     }
 
+    #[derive(Debug, Deserialize)]
+    pub struct Knights {
+        users: Vec<Knight>,
+    }
+
+    impl Deref for Knights {
+        type Target = Vec<Knight>;
+        fn deref(&self) -> &Self::Target {
+            &self.users
+        }
+    }
+
+    impl DerefMut for Knights {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.users
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Knight {
+        #[serde(flatten)]
+        user: Creator,
+        #[serde(rename = "comicsUploaded", default, deserialize_with="num_correct_deserializer")]
+        pub comics_uploaded: u64,
+    }
+
+    impl Deref for Knight {
+        type Target = Creator;
+        fn deref(&self) -> &Self::Target {
+            &self.user
+        }
+    }
+
+    impl DerefMut for Knight {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.user
+        }
+    }
+
     #[derive(Debug, Deserialize)]
     pub struct ComicMetadata {
         comic: _ComicMetadata,
@@ -235,6 +366,24 @@ pub mod responses {
         }
     }
 
+    #[derive(Debug, Deserialize)]
+    pub struct ComicList {
+        comics: Docs<Comic>,
+    }
+
+    impl Deref for ComicList {
+        type Target = Docs<Comic>;
+        fn deref(&self) -> &Self::Target {
+            &self.comics
+        }
+    }
+
+    impl DerefMut for ComicList {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.comics
+        }
+    }
+
     #[derive(Debug, Deserialize)]
     pub struct Comics {
         comics: Vec<Comic>,
@@ -363,6 +512,8 @@ pub mod responses {
         #[serde(deserialize_with="num_correct_deserializer")]
         pub level: u64,
         pub name: String,
+        pub avatar: Option<PictureDownloadResounce>,
+        pub slogan: Option<String>,
         pub title: String,
         #[serde(default)]
         pub verified: bool,
@@ -533,6 +684,36 @@ pub mod responses {
         pub total_comments: u64,
     }
 
+    #[derive(Debug, Deserialize)]
+    pub struct CommentTarget {
+        #[serde(rename = "_id")]
+        pub id: String,
+        pub title: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct MyComment {
+        #[serde(rename = "_id")]
+        pub id: String,
+        #[serde(rename = "_comic")]
+        pub comic: Option<CommentTarget>,
+        #[serde(rename = "_game")]
+        pub game: Option<CommentTarget>,
+        #[serde(default)]
+        pub content: String,
+        pub created_at: String,
+        #[serde(default)]
+        pub hide: bool,
+        #[serde(rename = "isLiked", default)]
+        pub is_liked: bool,
+        #[serde(rename = "likesCount", default, deserialize_with="num_correct_deserializer")]
+        pub likes_count: u64,
+        #[serde(rename = "commentsCount", default, deserialize_with="num_correct_deserializer")]
+        pub comments_count: u64,
+        #[serde(rename = "totalComments", default, deserialize_with="num_correct_deserializer")]
+        pub total_comments: u64,
+    }
+
     #[derive(Debug, Deserialize)]
     pub struct Page {
         #[serde(rename = "_id")]
@@ -666,6 +847,11 @@ pub mod responses {
         }
     }
 
+    #[derive(Debug, Deserialize)]
+    pub struct LikeAction {
+        pub action: String,
+    }
+
     #[derive(Debug, Deserialize)]
     pub struct GameComment {
         #[serde(rename = "_game")]
@@ -771,6 +957,90 @@ pub mod responses {
         }
     }
 
+    #[derive(Debug, Deserialize)]
+    pub struct Collection {
+        pub title: String,
+        pub comics: Vec<Comic>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Collections {
+        collections: Vec<Collection>,
+    }
+
+    impl Deref for Collections {
+        type Target = Vec<Collection>;
+        fn deref(&self) -> &Self::Target {
+            &self.collections
+        }
+    }
+
+    impl DerefMut for Collections {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.collections
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Banner {
+        #[serde(rename = "_id")]
+        pub id: String,
+        pub title: String,
+        #[serde(rename = "shortDescription")]
+        pub short_description: Option<String>,
+        #[serde(rename = "type")]
+        pub kind: String,
+        pub link: Option<String>,
+        #[serde(rename = "_comic")]
+        pub comic: Option<String>,
+        pub thumb: PictureDownloadResounce,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Banners {
+        banners: Vec<Banner>,
+    }
+
+    impl Deref for Banners {
+        type Target = Vec<Banner>;
+        fn deref(&self) -> &Self::Target {
+            &self.banners
+        }
+    }
+
+    impl DerefMut for Banners {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.banners
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct ChatRoom {
+        pub title: String,
+        pub description: Option<String>,
+        pub url: String,
+        pub avatar: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct ChatRooms {
+        #[serde(rename = "chatList")]
+        chat_list: Vec<ChatRoom>,
+    }
+
+    impl Deref for ChatRooms {
+        type Target = Vec<ChatRoom>;
+        fn deref(&self) -> &Self::Target {
+            &self.chat_list
+        }
+    }
+
+    impl DerefMut for ChatRooms {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.chat_list
+        }
+    }
+
     #[derive(Debug, Deserialize)]
     pub struct GameDownloadInfoP2p {
         pub bt: String,
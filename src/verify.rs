@@ -0,0 +1,188 @@
+use std::path::PathBuf;
+
+use tokio::{fs, io::AsyncReadExt};
+
+use crate::{
+    download::{ComicDownloader, DownloadReport, EpSelection},
+    error::Error,
+    manifest::Manifest,
+    ApiResult,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageKind {
+    Jpeg,
+    Png,
+    WebP,
+    Avif,
+}
+
+pub fn image_kind(header: &[u8]) -> Option<ImageKind> {
+    if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some(ImageKind::Jpeg)
+    } else if header.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+        Some(ImageKind::Png)
+    } else if header.len() >= 12 && &header[..4] == b"RIFF" && &header[8..12] == b"WEBP" {
+        Some(ImageKind::WebP)
+    } else if header.len() >= 12 && &header[4..8] == b"ftyp" && matches!(&header[8..12], b"avif" | b"avis") {
+        Some(ImageKind::Avif)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Problem {
+    Missing {
+        ep_id: String,
+        page_id: String,
+        path: PathBuf,
+    },
+    SizeMismatch {
+        ep_id: String,
+        page_id: String,
+        path: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    InvalidHeader {
+        ep_id: String,
+        page_id: String,
+        path: PathBuf,
+    },
+    PageCount {
+        ep_id: String,
+        expected: u64,
+        actual: u64,
+    },
+}
+
+impl Problem {
+    pub fn ep_id(&self) -> &str {
+        match self {
+            Self::Missing { ep_id, .. }
+            | Self::SizeMismatch { ep_id, .. }
+            | Self::InvalidHeader { ep_id, .. }
+            | Self::PageCount { ep_id, .. } => ep_id,
+        }
+    }
+
+    pub fn page_id(&self) -> Option<&str> {
+        match self {
+            Self::Missing { page_id, .. }
+            | Self::SizeMismatch { page_id, .. }
+            | Self::InvalidHeader { page_id, .. } => Some(page_id),
+            Self::PageCount { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct VerifyReport {
+    pub cid: String,
+    pub pages_checked: u64,
+    pub problems: Vec<Problem>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl ComicDownloader<'_> {
+    pub async fn verify(&self) -> ApiResult<VerifyReport> {
+        let manifest = self.load_manifest().await?;
+        let mut report = VerifyReport {
+            cid: self.cid().to_string(),
+            pages_checked: 0,
+            problems: Vec::new(),
+        };
+        for ep in manifest.eps.iter() {
+            let total = self.api().comic_pages(self.cid(), ep.order, 1).await?.total;
+            if ep.pages_total != total || ep.pages.len() as u64 != total {
+                report.problems.push(Problem::PageCount {
+                    ep_id: ep.id.clone(),
+                    expected: total,
+                    actual: ep.pages.len() as u64,
+                });
+            }
+            for page in ep.pages.iter() {
+                report.pages_checked += 1;
+                let path = self.root().join(&page.path);
+                let mut file = match fs::File::open(&path).await {
+                    Ok(file) => file,
+                    Err(_) => {
+                        report.problems.push(Problem::Missing {
+                            ep_id: ep.id.clone(),
+                            page_id: page.id.clone(),
+                            path,
+                        });
+                        continue;
+                    }
+                };
+                let actual = file.metadata().await?.len();
+                if actual != page.size {
+                    report.problems.push(Problem::SizeMismatch {
+                        ep_id: ep.id.clone(),
+                        page_id: page.id.clone(),
+                        path,
+                        expected: page.size,
+                        actual,
+                    });
+                    continue;
+                }
+                let mut header = Vec::with_capacity(12);
+                (&mut file).take(12).read_to_end(&mut header).await?;
+                if image_kind(&header).is_none() {
+                    report.problems.push(Problem::InvalidHeader {
+                        ep_id: ep.id.clone(),
+                        page_id: page.id.clone(),
+                        path,
+                    });
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    // Drops broken pages from the manifest and fetches only those again, leaving other eps
+    // alone even when the comic gained new ones. `None` when nothing was broken.
+    pub async fn repair(&self) -> ApiResult<Option<DownloadReport>> {
+        let report = self.verify().await?;
+        if report.is_ok() {
+            return Ok(None);
+        }
+        let mut manifest = self.load_manifest().await?;
+        for problem in report.problems.iter() {
+            let Some(ep) = manifest.eps.iter_mut().find(|ep| ep.id == problem.ep_id()) else {
+                continue;
+            };
+            match problem {
+                Problem::PageCount { expected, .. } => ep.pages_total = *expected,
+                _ => {
+                    let page_id = problem.page_id().unwrap_or_default();
+                    if let Some(position) = ep.pages.iter().position(|v| v.id == page_id) {
+                        let page = ep.pages.remove(position);
+                        let _ = fs::remove_file(self.root().join(page.path)).await;
+                    }
+                }
+            }
+            // forces the page listing of this ep to be fetched again
+            ep.updated_at = None;
+        }
+        manifest.save(&self.manifest_path()).await?;
+
+        let mut ids: Vec<String> = report.problems.iter().map(|v| v.ep_id().to_string()).collect();
+        ids.sort();
+        ids.dedup();
+        Ok(Some(self.run_selected(&EpSelection::Ids(ids)).await?))
+    }
+
+    async fn load_manifest(&self) -> ApiResult<Manifest> {
+        let path = self.manifest_path();
+        Manifest::load(&path)
+            .await?
+            .ok_or_else(|| Error::NotFound(path.to_string_lossy().to_string()))
+    }
+}